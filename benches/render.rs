@@ -0,0 +1,69 @@
+//! Throughput of `Visualizer::render` on a fixed sine buffer, rendered into a
+//! sink. Run with `cargo bench`; pass a mode name (`waveform`, `spectrum`, ...)
+//! to time only that mode.
+//!
+//! Every mode is timed at 64 bars on 4096 samples, and waveform mode also at
+//! 256 bars on a buffer large enough for the `parallel` feature to split the
+//! work across threads.
+
+use std::hint::black_box;
+use std::io;
+
+use clap::ValueEnum;
+use criterion::measurement::WallTime;
+use criterion::{BenchmarkGroup, Criterion, Throughput, criterion_group, criterion_main};
+use echoes::{Visualizer, VisualizerMode};
+
+const SAMPLES: usize = 4096;
+const LARGE_SAMPLES: usize = 1 << 18;
+const SAMPLE_RATE: f32 = 44_100.0;
+const BARS: usize = 64;
+const MANY_BARS: usize = 256;
+
+fn sine(len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|n| 0.8 * (std::f32::consts::TAU * 440.0 * n as f32 / SAMPLE_RATE).sin())
+        .collect()
+}
+
+fn mode_name(mode: VisualizerMode) -> String {
+    mode.to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+// Time frames rendered from `samples` into a sink, counted in samples.
+fn bench(
+    group: &mut BenchmarkGroup<WallTime>,
+    name: &str,
+    mode: VisualizerMode,
+    bars: usize,
+    samples: &[f32],
+) {
+    let mut visualizer = Visualizer::new(mode).with_bars(bars);
+    let mut sink = io::sink();
+    group.throughput(Throughput::Elements(samples.len() as u64));
+    group.bench_function(name, |b| {
+        b.iter(|| {
+            visualizer
+                .render(&mut sink, black_box(samples))
+                .expect("writing to a sink can't fail")
+        })
+    });
+}
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    let small = sine(SAMPLES);
+    for &mode in VisualizerMode::value_variants() {
+        bench(&mut group, &mode_name(mode), mode, BARS, &small);
+    }
+
+    let large = sine(LARGE_SAMPLES);
+    let mode = VisualizerMode::Waveform;
+    bench(&mut group, "waveform/large", mode, MANY_BARS, &large);
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
@@ -0,0 +1,246 @@
+//! The level math behind the waveform, scope and mirror modes: slicing a buffer
+//! into bars, measuring each slice and easing levels between frames. It needs
+//! only `core` and `alloc`, so with default features off the crate builds for
+//! `no_std` targets, such as a microcontroller driving an LED strip, with just
+//! this module.
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use clap::ValueEnum;
+use libm::sqrtf;
+
+// Below this many samples, handing bars to the thread pool costs more than the
+// levels take.
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_SAMPLES: usize = 1 << 16;
+
+/// How waveform mode measures the slice of samples behind each bar, on each
+/// side of the centerline.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(ValueEnum))]
+pub enum LevelMode {
+    /// Three parts peak to one part mean magnitude: lively without being spiky.
+    #[default]
+    PeakAvg,
+    /// Root mean square, the smoothest and closest to perceived loudness.
+    Rms,
+    /// The largest sample, the most responsive to transients.
+    Peak,
+}
+
+/// Append the level of each of `num_bars` equal slices of `samples` to
+/// `columns`, measured by `mode` on each side of zero.
+pub fn waveform_columns(
+    samples: &[f32],
+    num_bars: usize,
+    mode: LevelMode,
+    columns: &mut Vec<(f32, f32)>,
+) {
+    if num_bars == 0 {
+        return;
+    }
+
+    let len = samples.len();
+    let chunk_size = len.div_ceil(num_bars);
+    // Whole chunks of `chunk_size` run out before the last bars when the
+    // buffer is short for the bar count (1-63 samples over 64 bars). Then
+    // each bar takes its share of the buffer instead, repeating samples
+    // when there are fewer of them than bars.
+    let whole_chunks = (num_bars - 1) * chunk_size < len;
+    let level = |i: usize| {
+        let (start, end) = if whole_chunks {
+            let start = i * chunk_size;
+            (start, (start + chunk_size).min(len))
+        } else {
+            let start = i * len / num_bars;
+            (start, ((i + 1) * len / num_bars).max(start + 1))
+        };
+        if start >= len {
+            return (0.0, 0.0);
+        }
+        chunk_levels(&samples[start..end], mode)
+    };
+
+    // Each bar's chunk is independent, so big buffers are split across threads.
+    #[cfg(feature = "parallel")]
+    if samples.len() >= PARALLEL_MIN_SAMPLES {
+        use rayon::prelude::*;
+        columns.par_extend((0..num_bars).into_par_iter().map(level));
+        return;
+    }
+
+    columns.extend((0..num_bars).map(level));
+}
+
+/// Append every `len / num_bars`-th sample, split into `(positive, negative)`
+/// parts so a trace shares the bar modes' column format.
+pub fn scope_columns(samples: &[f32], num_bars: usize, columns: &mut Vec<(f32, f32)>) {
+    columns.extend((0..num_bars).map(|i| {
+        let sample = samples.get(i * samples.len() / num_bars).copied();
+        let sample = sample.unwrap_or(0.0);
+        (sample.max(0.0), (-sample).max(0.0))
+    }));
+}
+
+/// The level of one bar's chunk of samples, `(positive, negative)`, each taken
+/// over the samples of that sign as `mode` says.
+pub fn chunk_levels(chunk: &[f32], mode: LevelMode) -> (f32, f32) {
+    let mut pos_peak = 0.0f32;
+    let mut pos_sum = 0.0f32;
+    let mut pos_squares = 0.0f32;
+    let mut pos_count = 0u32;
+    let mut neg_peak = 0.0f32;
+    let mut neg_sum = 0.0f32;
+    let mut neg_squares = 0.0f32;
+    let mut neg_count = 0u32;
+
+    for &sample in chunk {
+        if sample > 0.0 {
+            pos_peak = pos_peak.max(sample);
+            pos_sum += sample;
+            pos_squares += sample * sample;
+            pos_count += 1;
+        } else if sample < 0.0 {
+            let magnitude = -sample;
+            neg_peak = neg_peak.max(magnitude);
+            neg_sum += magnitude;
+            neg_squares += magnitude * magnitude;
+            neg_count += 1;
+        }
+    }
+
+    let level = |peak: f32, sum: f32, squares: f32, count: u32| {
+        if count == 0 {
+            return 0.0;
+        }
+        match mode {
+            LevelMode::PeakAvg => 0.75 * peak + 0.25 * sum / count as f32,
+            LevelMode::Rms => sqrtf(squares / count as f32),
+            LevelMode::Peak => peak,
+        }
+    };
+
+    (
+        level(pos_peak, pos_sum, pos_squares, pos_count),
+        level(neg_peak, neg_sum, neg_squares, neg_count),
+    )
+}
+
+/// Root mean square of `samples`, or 0 for none.
+pub fn rms(samples: &[f32]) -> f32 {
+    let sum: f32 = samples.iter().map(|&sample| sample * sample).sum();
+    sqrtf(sum / samples.len().max(1) as f32)
+}
+
+/// Move a bar from `prev` toward `level`: `rise` of the way if it's going up,
+/// `fall` if down, 1.0 jumping straight there.
+pub fn ease(level: f32, prev: f32, rise: f32, fall: f32) -> f32 {
+    let blend = if level > prev { rise } else { fall };
+    blend * level + (1.0 - blend) * prev
+}
+
+/// Append `width` bars to `columns` laid out from `bands`, with band 0 in the
+/// middle and each following band one step further out on both sides.
+pub fn mirror(bands: &[(f32, f32)], width: usize, columns: &mut Vec<(f32, f32)>) {
+    let center = width / 2;
+    columns.extend((0..width).map(|i| {
+        let distance = if width % 2 == 1 || i >= center {
+            i.abs_diff(center)
+        } else {
+            center - 1 - i
+        };
+        bands.get(distance).copied().unwrap_or((0.0, 0.0))
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec;
+
+    #[test]
+    fn chunk_levels_of_empty_chunk_is_silent() {
+        assert_eq!(chunk_levels(&[], LevelMode::PeakAvg), (0.0, 0.0));
+    }
+
+    #[test]
+    fn chunk_levels_of_silence_is_silent() {
+        assert_eq!(chunk_levels(&[0.0; 64], LevelMode::PeakAvg), (0.0, 0.0));
+    }
+
+    #[test]
+    fn chunk_levels_of_positive_dc() {
+        let (pos, neg) = chunk_levels(&[0.5; 64], LevelMode::PeakAvg);
+        assert!((pos - 0.5).abs() < 1e-6);
+        assert_eq!(neg, 0.0);
+    }
+
+    #[test]
+    fn chunk_levels_of_negative_dc() {
+        let (pos, neg) = chunk_levels(&[-0.5; 64], LevelMode::PeakAvg);
+        assert_eq!(pos, 0.0);
+        assert!((neg - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn chunk_levels_blends_peak_and_average() {
+        let (pos, _) = chunk_levels(&[1.0, 0.2, 0.2, 0.2], LevelMode::PeakAvg);
+        assert!((pos - (0.75 * 1.0 + 0.25 * 0.4)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn chunk_levels_by_rms_and_by_peak() {
+        let chunk = [1.0, 0.2, 0.2, 0.2, -0.5, -0.5];
+        let (pos, neg) = chunk_levels(&chunk, LevelMode::Rms);
+        assert!((pos - sqrtf(1.12 / 4.0)).abs() < 1e-6);
+        assert!((neg - 0.5).abs() < 1e-6);
+        assert_eq!(chunk_levels(&chunk, LevelMode::Peak), (1.0, 0.5));
+    }
+
+    #[test]
+    fn chunk_levels_of_full_scale_sine() {
+        let sine: Vec<f32> = (0..1024)
+            .map(|n| libm::sinf(core::f32::consts::TAU * n as f32 / 1024.0))
+            .collect();
+        let (pos, neg) = chunk_levels(&sine, LevelMode::PeakAvg);
+
+        // Peak 1.0, mean magnitude 2/pi over each half cycle.
+        let expected = 0.75 + 0.25 * core::f32::consts::FRAC_2_PI;
+        assert!((pos - expected).abs() < 1e-3, "pos = {}", pos);
+        assert!((neg - expected).abs() < 1e-3, "neg = {}", neg);
+    }
+
+    #[test]
+    fn waveform_columns_of_a_large_buffer_follow_each_chunk() {
+        // Big enough to take the threaded path with the `parallel` feature.
+        let samples: Vec<f32> = (0..1 << 17)
+            .map(|n| ((n % 97) as f32 - 48.0) / 48.0)
+            .collect();
+        let mut columns = vec![(9.0, 9.0)];
+        waveform_columns(&samples, 100, LevelMode::PeakAvg, &mut columns);
+
+        let chunk_size = samples.len().div_ceil(100);
+        let expected: Vec<(f32, f32)> = samples
+            .chunks(chunk_size)
+            .map(|chunk| chunk_levels(chunk, LevelMode::PeakAvg))
+            .collect();
+        assert_eq!(columns[0], (9.0, 9.0));
+        assert_eq!(&columns[1..], expected);
+    }
+
+    #[test]
+    fn mirror_puts_the_first_band_in_the_middle() {
+        let bands = [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)];
+        let levels = |width| {
+            let mut columns = Vec::new();
+            mirror(&bands, width, &mut columns);
+            columns.iter().map(|c| c.0).collect::<Vec<_>>()
+        };
+        assert_eq!(levels(5), [2.0, 1.0, 0.0, 1.0, 2.0]);
+        assert_eq!(levels(6), [2.0, 1.0, 0.0, 0.0, 1.0, 2.0]);
+        assert_eq!(levels(1), [0.0]);
+        assert!(levels(0).is_empty());
+    }
+}
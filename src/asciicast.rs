@@ -0,0 +1,135 @@
+//! Recording terminal output as an asciinema v2 `.cast` file.
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::terminal::terminal_layout;
+use crate::visualizer::{DEFAULT_BARS, DEFAULT_HEIGHT};
+
+/// A writer that passes everything on to `out` and records it to `cast` as an
+/// asciicast: a JSON header line, then a `[seconds, "o", text]` output event
+/// for each flush, timed from when recording started. Frames are flushed as
+/// they're drawn, so each one becomes an event at the moment it was shown.
+pub struct CastRecorder<W: Write, R: Write> {
+    out: W,
+    cast: R,
+    started: Instant,
+    // Output not recorded yet. A character cut off by a flush waits here for
+    // the rest of its bytes.
+    pending: Vec<u8>,
+}
+
+impl<W: Write, R: Write> CastRecorder<W, R> {
+    /// Start recording what's written to `out`, headed with the terminal's
+    /// size, or the size frames are drawn at when stdout isn't a terminal.
+    pub fn new(out: W, mut cast: R) -> io::Result<Self> {
+        let (width, height) = terminal_layout().unwrap_or((DEFAULT_BARS, DEFAULT_HEIGHT));
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        // Frames leave the last row for their trailing newline.
+        writeln!(
+            cast,
+            "{{\"version\":2,\"width\":{},\"height\":{},\"timestamp\":{}}}",
+            width,
+            height + 1,
+            timestamp
+        )?;
+
+        Ok(Self {
+            out,
+            cast,
+            started: Instant::now(),
+            pending: Vec::new(),
+        })
+    }
+}
+
+impl<W: Write, R: Write> Write for CastRecorder<W, R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.out.write(buf)?;
+        self.pending.extend_from_slice(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()?;
+        let end = match std::str::from_utf8(&self.pending) {
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            _ => self.pending.len(),
+        };
+        if end == 0 {
+            return Ok(());
+        }
+
+        let text = String::from_utf8_lossy(&self.pending[..end]);
+        writeln!(
+            self.cast,
+            "[{:.6},\"o\",{}]",
+            self.started.elapsed().as_secs_f64(),
+            json_string(&text)
+        )?;
+        self.pending.drain(..end);
+        Ok(())
+    }
+}
+
+// `text` as a quoted JSON string, with quotes, backslashes and control
+// characters escaped.
+fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_characters_are_escaped() {
+        assert_eq!(
+            json_string("\x1B[2J\"a\\b\"\r\n█"),
+            "\"\\u001b[2J\\\"a\\\\b\\\"\\r\\n█\""
+        );
+    }
+
+    #[test]
+    fn each_flush_is_one_event() {
+        let mut out = Vec::new();
+        let mut cast = Vec::new();
+        let mut recorder = CastRecorder::new(&mut out, &mut cast).unwrap();
+        recorder.write_all(b"ab").unwrap();
+        recorder.write_all(b"c").unwrap();
+        recorder.flush().unwrap();
+        // Half of a two-byte character waits for the other half.
+        recorder.write_all(&"é".as_bytes()[..1]).unwrap();
+        recorder.flush().unwrap();
+        recorder.write_all(&"é".as_bytes()[1..]).unwrap();
+        recorder.flush().unwrap();
+        drop(recorder);
+
+        assert_eq!(out, "abcé".as_bytes());
+        let cast = String::from_utf8(cast).unwrap();
+        let lines: Vec<&str> = cast.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("{\"version\":2,\"width\":"));
+        assert!(lines[1].starts_with('[') && lines[1].ends_with(",\"o\",\"abc\"]"));
+        assert!(lines[2].ends_with(",\"o\",\"é\"]"));
+    }
+}
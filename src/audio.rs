@@ -0,0 +1,157 @@
+//! Speaker output for decoded packets, behind the `audio` feature.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use symphonia::core::audio::{AudioBufferRef, SampleBuffer};
+
+// Interleaved samples waiting for the output callback.
+type Queue = Arc<Mutex<VecDeque<f32>>>;
+
+/// Plays buffers pushed from the decode loop on the default output device.
+pub struct AudioOutput {
+    // Playback stops when the stream is dropped.
+    stream: Stream,
+    queue: Queue,
+    channels: usize,
+    scratch: Option<SampleBuffer<f32>>,
+}
+
+impl AudioOutput {
+    /// Open the default output device for `channels` interleaved channels at `sample_rate`.
+    pub fn open(sample_rate: u32, channels: usize) -> Result<Self, Box<dyn Error>> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no audio output device")?;
+
+        // Prefer f32 output so samples go straight through without conversion.
+        let supported = device
+            .supported_output_configs()?
+            .filter(|range| range.channels() as usize == channels)
+            .filter_map(|range| range.try_with_sample_rate(sample_rate))
+            .max_by_key(|config| config.sample_format() == SampleFormat::F32)
+            .ok_or_else(|| {
+                format!(
+                    "output device can't play {} channels at {} Hz",
+                    channels, sample_rate
+                )
+            })?;
+
+        let queue = Queue::default();
+        let config = supported.config();
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => build_stream::<f32>(&device, config, &queue)?,
+            SampleFormat::I16 => build_stream::<i16>(&device, config, &queue)?,
+            SampleFormat::U16 => build_stream::<u16>(&device, config, &queue)?,
+            SampleFormat::I32 => build_stream::<i32>(&device, config, &queue)?,
+            other => return Err(format!("unsupported output sample format {}", other).into()),
+        };
+        stream.play()?;
+
+        Ok(Self {
+            stream,
+            queue,
+            channels,
+            scratch: None,
+        })
+    }
+
+    /// Halt or resume the device; anything already queued plays on resume.
+    pub fn set_paused(&self, paused: bool) {
+        let result = if paused {
+            self.stream.pause()
+        } else {
+            self.stream.play()
+        };
+        if let Err(err) = result {
+            warn!("audio output: {}", err);
+        }
+    }
+
+    /// Drop everything queued but not yet played, as after a seek.
+    pub fn clear(&self) {
+        if let Ok(mut queue) = self.queue.lock() {
+            queue.clear();
+        }
+    }
+
+    /// Block until at most `frames` frames are left for the device to play, so
+    /// the caller keeps pace with the device's clock rather than the wall
+    /// clock. Gives up once the device has taken nothing for a second, as when
+    /// it has stopped.
+    pub fn wait_until_queued(&self, frames: usize) {
+        const POLL: Duration = Duration::from_millis(2);
+        const STALLED: Duration = Duration::from_secs(1);
+
+        let limit = frames * self.channels;
+        let mut last = usize::MAX;
+        let mut since = Instant::now();
+        loop {
+            let queued = self.queue.lock().map_or(0, |queue| queue.len());
+            if queued <= limit || crate::interrupted() {
+                return;
+            }
+            if queued != last {
+                last = queued;
+                since = Instant::now();
+            } else if since.elapsed() >= STALLED {
+                return;
+            }
+            std::thread::sleep(POLL);
+        }
+    }
+
+    /// Queue a decoded buffer for playback.
+    pub fn push(&mut self, decoded: AudioBufferRef) {
+        let needed = decoded.capacity() * decoded.spec().channels.count();
+        if self
+            .scratch
+            .as_ref()
+            .is_none_or(|buf| buf.capacity() < needed)
+        {
+            self.scratch = Some(SampleBuffer::new(
+                decoded.capacity() as u64,
+                *decoded.spec(),
+            ));
+        }
+
+        if let Some(scratch) = self.scratch.as_mut() {
+            scratch.copy_interleaved_ref(decoded);
+            if let Ok(mut queue) = self.queue.lock() {
+                queue.extend(scratch.samples());
+            }
+        }
+    }
+}
+
+fn build_stream<T>(
+    device: &Device,
+    config: StreamConfig,
+    queue: &Queue,
+) -> Result<Stream, Box<dyn Error>>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let queue = Arc::clone(queue);
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let mut queue = queue.lock().ok();
+            for slot in data.iter_mut() {
+                // Pad with silence when decoding falls behind.
+                let sample = queue.as_mut().and_then(|q| q.pop_front()).unwrap_or(0.0);
+                *slot = T::from_sample(sample);
+            }
+        },
+        |err| warn!("audio output: {}", err),
+        None,
+    )?;
+
+    Ok(stream)
+}
@@ -0,0 +1,129 @@
+//! A dot canvas drawn with Unicode Braille patterns, 2x4 dots per cell.
+
+// Bit for the dot at `(x % 2, y % 4)` within a cell, per the Braille block's layout.
+pub(crate) const DOT_BITS: [[u8; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+/// A grid of terminal cells addressed by dot, 2 dots across and 4 down per cell.
+pub(crate) struct BrailleCanvas {
+    width: usize,
+    height: usize,
+    // One dot pattern per cell, row-major.
+    cells: Vec<u8>,
+}
+
+impl BrailleCanvas {
+    /// A blank canvas `width` cells across and `height` cells down.
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![0; width * height],
+        }
+    }
+
+    /// Canvas size in dots.
+    pub(crate) fn dots(&self) -> (usize, usize) {
+        (self.width * 2, self.height * 4)
+    }
+
+    /// Raise the dot at `(x, y)`, counted from the top left. Dots off the canvas
+    /// are ignored.
+    pub(crate) fn set(&mut self, x: usize, y: usize) {
+        if x < self.width * 2 && y < self.height * 4 {
+            self.cells[(y / 4) * self.width + x / 2] |= DOT_BITS[x % 2][y % 4];
+        }
+    }
+
+    /// Mirror every dot top to bottom.
+    pub(crate) fn flip(&mut self) {
+        let rows: Vec<&[u8]> = self.cells.chunks(self.width.max(1)).rev().collect();
+        self.cells = rows.concat().into_iter().map(flip_cell).collect();
+    }
+
+    /// One string of Braille characters per row of cells, top to bottom.
+    pub(crate) fn render(&self) -> Vec<String> {
+        self.cells
+            .chunks(self.width.max(1))
+            .take(self.height)
+            .map(|row| row.iter().map(|&bits| braille_char(bits)).collect())
+            .collect()
+    }
+}
+
+// A cell's dot pattern upside down.
+fn flip_cell(bits: u8) -> u8 {
+    let mut flipped = 0;
+    for column in DOT_BITS {
+        for (y, &bit) in column.iter().enumerate() {
+            if bits & bit != 0 {
+                flipped |= column[3 - y];
+            }
+        }
+    }
+    flipped
+}
+
+fn braille_char(bits: u8) -> char {
+    char::from_u32(0x2800 + bits as u32).unwrap_or(' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blank_canvas_renders_empty_patterns() {
+        let canvas = BrailleCanvas::new(3, 2);
+        assert_eq!(canvas.render(), vec!["\u{2800}".repeat(3); 2]);
+    }
+
+    #[test]
+    fn dots_map_to_their_bits() {
+        let mut canvas = BrailleCanvas::new(1, 1);
+        canvas.set(0, 0);
+        canvas.set(1, 3);
+        assert_eq!(canvas.render(), vec!["\u{2881}"]);
+    }
+
+    #[test]
+    fn full_cell_is_all_dots() {
+        let mut canvas = BrailleCanvas::new(1, 1);
+        for x in 0..2 {
+            for y in 0..4 {
+                canvas.set(x, y);
+            }
+        }
+        assert_eq!(canvas.render(), vec!["\u{28FF}"]);
+    }
+
+    #[test]
+    fn dots_land_in_the_right_cell() {
+        let mut canvas = BrailleCanvas::new(2, 2);
+        canvas.set(2, 4);
+        assert_eq!(
+            canvas.render(),
+            vec!["\u{2800}\u{2800}", "\u{2800}\u{2801}"]
+        );
+    }
+
+    #[test]
+    fn flipping_mirrors_rows_and_dots() {
+        let mut canvas = BrailleCanvas::new(1, 2);
+        canvas.set(0, 0);
+        canvas.set(1, 6);
+        canvas.flip();
+
+        let mut expected = BrailleCanvas::new(1, 2);
+        expected.set(0, 7);
+        expected.set(1, 1);
+        assert_eq!(canvas.render(), expected.render());
+    }
+
+    #[test]
+    fn dots_off_the_canvas_are_ignored() {
+        let mut canvas = BrailleCanvas::new(1, 1);
+        canvas.set(2, 0);
+        canvas.set(0, 4);
+        assert_eq!(canvas.render(), vec!["\u{2800}"]);
+    }
+}
@@ -0,0 +1,286 @@
+//! Visualizing live audio captured from the system, behind the `audio` feature.
+
+use std::error::Error;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, Host, SampleFormat, SizedSample, Stream, StreamConfig};
+
+use crate::decode::{ChannelMode, DecodeError, MonoMix, Outcome, PlayOptions};
+use crate::interrupted;
+use crate::terminal::{Command, read_commands};
+use crate::visualizer::Visualizer;
+
+// Frames drawn per second without `PlayOptions::fps`.
+const DEFAULT_FPS: u32 = 60;
+
+// Interleaved samples captured since the last frame.
+type Queue = Arc<Mutex<Vec<f32>>>;
+
+/// Where live audio comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LiveSource {
+    /// Whatever the default output device is playing, where the platform can
+    /// capture it.
+    Loopback,
+    /// The default input device, such as a microphone or line in.
+    Microphone,
+}
+
+// An input stream filling a queue from the device's callback.
+struct Capture {
+    // Capture stops when the stream is dropped.
+    _stream: Stream,
+    queue: Queue,
+    name: String,
+    rate: u32,
+    channels: usize,
+}
+
+impl Capture {
+    fn open_source(source: LiveSource) -> Result<Self, Box<dyn Error>> {
+        let host = cpal::default_host();
+        let (device, config) = match source {
+            LiveSource::Loopback => loopback_device(&host)?,
+            LiveSource::Microphone => {
+                let device = host.default_input_device().ok_or("no audio input device")?;
+                let config = device.default_input_config()?;
+                (device, config)
+            }
+        };
+        Self::open(device, config.sample_format(), config.config())
+    }
+
+    fn open(
+        device: Device,
+        format: SampleFormat,
+        config: StreamConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        let queue = Queue::default();
+        // Kept to a second of audio, in case frames stop being drawn.
+        let limit = config.sample_rate as usize * config.channels as usize;
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, config, &queue, limit)?,
+            SampleFormat::I16 => build_stream::<i16>(&device, config, &queue, limit)?,
+            SampleFormat::U16 => build_stream::<u16>(&device, config, &queue, limit)?,
+            SampleFormat::I32 => build_stream::<i32>(&device, config, &queue, limit)?,
+            other => return Err(format!("unsupported input sample format {}", other).into()),
+        };
+        stream.play()?;
+
+        Ok(Self {
+            _stream: stream,
+            queue,
+            name: device.to_string(),
+            rate: config.sample_rate,
+            channels: config.channels.max(1) as usize,
+        })
+    }
+
+    // Move everything captured so far into `samples`.
+    fn take(&self, samples: &mut Vec<f32>) {
+        samples.clear();
+        if let Ok(mut queue) = self.queue.lock() {
+            samples.append(&mut queue);
+        }
+    }
+}
+
+// WASAPI, and CoreAudio from macOS 14.6, record what an output device plays
+// when it's opened for input.
+#[cfg(any(windows, target_os = "macos"))]
+fn loopback_device(host: &Host) -> Result<(Device, cpal::SupportedStreamConfig), Box<dyn Error>> {
+    let device = host
+        .default_output_device()
+        .ok_or("no audio output device to capture")?;
+    let config = device.default_output_config()?;
+    Ok((device, config))
+}
+
+// Elsewhere the sound server has to offer a monitor of the output as an input,
+// as PulseAudio and PipeWire can.
+#[cfg(not(any(windows, target_os = "macos")))]
+fn loopback_device(host: &Host) -> Result<(Device, cpal::SupportedStreamConfig), Box<dyn Error>> {
+    let device = host
+        .input_devices()?
+        .find(|device| device.to_string().to_lowercase().contains("monitor"))
+        .ok_or("loopback capture isn't available here: no monitor input device found")?;
+    let config = device.default_input_config()?;
+    Ok((device, config))
+}
+
+fn build_stream<T>(
+    device: &Device,
+    config: StreamConfig,
+    queue: &Queue,
+    limit: usize,
+) -> Result<Stream, Box<dyn Error>>
+where
+    T: SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    let queue = Arc::clone(queue);
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _| {
+            if let Ok(mut queue) = queue.lock() {
+                queue.extend(data.iter().map(|&sample| sample.to_sample::<f32>()));
+                let excess = queue.len().saturating_sub(limit);
+                queue.drain(..excess);
+            }
+        },
+        |err| warn!("audio capture: {}", err),
+        None,
+    )?;
+
+    Ok(stream)
+}
+
+/// Visualize live audio from `source` in its device's own format and layout,
+/// until interrupted, quit or [`PlayOptions::stop_at`]. Frames are drawn
+/// `options.fps` times a second, or 60 without it; playback and seeking
+/// options don't apply. A device that can't be opened, or a platform that
+/// can't capture its output, fails with [`DecodeError::Capture`].
+pub fn play_live<W: Write>(
+    source: LiveSource,
+    visualizer: &mut Visualizer,
+    options: &PlayOptions,
+    out: &mut W,
+) -> Result<Outcome, DecodeError> {
+    let capture =
+        Capture::open_source(source).map_err(|err| DecodeError::Capture(err.to_string()))?;
+    debug!(
+        "capturing {}: {} channels at {} Hz",
+        capture.name, capture.channels, capture.rate
+    );
+
+    visualizer.reset();
+    let label = match source {
+        LiveSource::Loopback => "loopback",
+        LiveSource::Microphone => "input",
+    };
+    visualizer.set_header(Some(format!("{}: {}", label, capture.name)));
+    visualizer.set_sample_rate(capture.rate);
+
+    let interval = Duration::from_secs_f64(1.0 / options.fps.unwrap_or(DEFAULT_FPS).max(1) as f64);
+    let started = Instant::now();
+    let mut paused = false;
+    let mut captured = Vec::new();
+    let mut pending: [Vec<f32>; 2] = Default::default();
+    while !interrupted() {
+        if options.stop_at.is_some_and(|at| Instant::now() >= at) {
+            return Ok(Outcome::TimeUp);
+        }
+        if options.controls {
+            for command in read_commands() {
+                match command {
+                    Command::TogglePause => paused = !paused,
+                    Command::NextMode => visualizer.set_mode(visualizer.mode().next()),
+                    Command::Quit => return Ok(Outcome::Quit),
+                    Command::SeekBackward | Command::SeekForward => {}
+                }
+            }
+        }
+        std::thread::sleep(interval);
+
+        // Paused, the display holds still and what's captured is let go.
+        capture.take(&mut captured);
+        if paused || captured.is_empty() {
+            continue;
+        }
+        split(&captured, capture.channels, options, &mut pending);
+        visualizer.set_progress(started.elapsed(), None);
+        match options.channel_mode {
+            ChannelMode::Stereo if options.channel.is_none() => {
+                visualizer.render_stereo(out, &pending[0], &pending[1])?
+            }
+            _ => visualizer.render(out, &pending[0])?,
+        }
+    }
+    Ok(Outcome::Finished)
+}
+
+// Lay out `interleaved` samples of `channels` channels as the decode loop does:
+// one channel, or both halves of stereo, or the mono mix in `pending[0]`. With
+// no channel layout to go by, the ITU mix is a plain average.
+fn split(interleaved: &[f32], channels: usize, options: &PlayOptions, pending: &mut [Vec<f32>; 2]) {
+    pending.iter_mut().for_each(Vec::clear);
+    let frames = interleaved.chunks_exact(channels);
+    let pick = |channel: usize| {
+        let channel = if channel < channels { channel } else { 0 };
+        frames.clone().map(move |frame| frame[channel])
+    };
+    match (options.channel, options.channel_mode, options.mono_mix) {
+        (Some(channel), _, _) => pending[0].extend(pick(channel)),
+        (None, ChannelMode::Stereo, _) => {
+            pending[0].extend(pick(0));
+            pending[1].extend(pick(1));
+        }
+        (None, ChannelMode::Mono, MonoMix::Left) => pending[0].extend(pick(0)),
+        (None, ChannelMode::Mono, MonoMix::Right) => pending[0].extend(pick(1)),
+        (None, ChannelMode::Mono, MonoMix::Average | MonoMix::Itu) => pending[0].extend(
+            frames
+                .clone()
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(channel_mode: ChannelMode, mono_mix: MonoMix) -> PlayOptions {
+        PlayOptions {
+            audio: false,
+            channel_mode,
+            mono_mix,
+            channel: None,
+            controls: false,
+            looping: false,
+            gapless: false,
+            start: None,
+            end: None,
+            stop_at: None,
+            fps: None,
+            window: None,
+            hop: None,
+            realtime: true,
+        }
+    }
+
+    #[test]
+    fn captured_frames_split_like_decoded_ones() {
+        let interleaved = [0.5, -0.5, 1.0, 0.0];
+        let mut pending = Default::default();
+
+        split(
+            &interleaved,
+            2,
+            &options(ChannelMode::Stereo, MonoMix::Average),
+            &mut pending,
+        );
+        assert_eq!(pending, [vec![0.5, 1.0], vec![-0.5, 0.0]]);
+
+        split(
+            &interleaved,
+            2,
+            &options(ChannelMode::Mono, MonoMix::Average),
+            &mut pending,
+        );
+        assert_eq!(pending, [vec![0.0, 0.5], vec![]]);
+
+        // A mono device has no right channel to pick.
+        split(
+            &[0.25, 0.75],
+            1,
+            &options(ChannelMode::Mono, MonoMix::Right),
+            &mut pending,
+        );
+        assert_eq!(pending[0], [0.25, 0.75]);
+    }
+}
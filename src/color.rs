@@ -0,0 +1,114 @@
+//! Color themes and the ANSI palettes they're drawn with.
+
+use clap::ValueEnum;
+
+/// Which ANSI color escapes the terminal understands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ColorDepth {
+    /// The xterm 256-color palette.
+    #[value(name = "256")]
+    Ansi256,
+    /// 24-bit RGB.
+    Truecolor,
+}
+
+impl ColorDepth {
+    /// Truecolor when the terminal says so via `$COLORTERM`, else the 256-color palette.
+    pub fn detect() -> Self {
+        match std::env::var("COLORTERM") {
+            Ok(value) if value == "truecolor" || value == "24bit" => ColorDepth::Truecolor,
+            _ => ColorDepth::Ansi256,
+        }
+    }
+}
+
+/// Color ramp that maps a bar's level to a color.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Theme {
+    /// Teal through green and yellow to red.
+    #[default]
+    Classic,
+    /// Embers to white-hot.
+    Fire,
+    /// Deep blue to surf.
+    Ocean,
+    /// Violet to red across the spectrum.
+    Rainbow,
+    /// The perceptually uniform matplotlib map.
+    Viridis,
+    /// No color codes at all.
+    Mono,
+}
+
+impl Theme {
+    // Gradient stops from quietest to loudest; empty for `Mono`.
+    pub(crate) fn stops(self) -> &'static [(u8, u8, u8)] {
+        match self {
+            Theme::Classic => &[
+                (0, 175, 255), // teal
+                (0, 255, 135), // green
+                (215, 255, 0), // yellow
+                (255, 135, 0), // orange
+                (255, 0, 0),   // red
+            ],
+            Theme::Fire => &[
+                (95, 0, 0),
+                (215, 0, 0),
+                (255, 135, 0),
+                (255, 215, 0),
+                (255, 255, 215),
+            ],
+            Theme::Ocean => &[
+                (0, 0, 135),
+                (0, 95, 215),
+                (0, 175, 255),
+                (95, 255, 215),
+                (215, 255, 255),
+            ],
+            Theme::Rainbow => &[
+                (135, 0, 255),
+                (0, 95, 255),
+                (0, 215, 0),
+                (255, 255, 0),
+                (255, 135, 0),
+                (255, 0, 0),
+            ],
+            Theme::Viridis => &[
+                (68, 1, 84),
+                (59, 82, 139),
+                (33, 145, 140),
+                (94, 201, 98),
+                (253, 231, 37),
+            ],
+            Theme::Mono => &[],
+        }
+    }
+}
+
+// Smooth interpolation between gradient stops at `t` in 0..1.
+pub(crate) fn blend(stops: &[(u8, u8, u8)], t: f32) -> (u8, u8, u8) {
+    if stops.len() == 1 {
+        return stops[0];
+    }
+
+    let scaled = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let i = (scaled.floor() as usize).min(stops.len() - 2);
+    let t = scaled - i as f32;
+    let (r0, g0, b0) = stops[i];
+    let (r1, g1, b1) = stops[i + 1];
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+
+    (lerp(r0, r1), lerp(g0, g1), lerp(b0, b1))
+}
+
+// Nearest entry in the xterm 6x6x6 color cube.
+pub(crate) fn ansi256_index(r: u8, g: u8, b: u8) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let nearest = |c: u8| {
+        (0..LEVELS.len())
+            .min_by_key(|&i| (LEVELS[i] as i16 - c as i16).abs())
+            .unwrap_or(0) as u8
+    };
+
+    16 + 36 * nearest(r) + 6 * nearest(g) + nearest(b)
+}
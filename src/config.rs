@@ -0,0 +1,585 @@
+//! Everything that shapes a [`Visualizer`](crate::Visualizer), gathered in one
+//! value that can be built up, stored and handed over whole.
+
+use std::fmt;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+
+use crate::analysis::LevelMode;
+use crate::color::{ColorDepth, Theme};
+use crate::loudness::Normalization;
+use crate::spectrum::{Weighting, WindowFunction};
+use crate::visualizer::{Centerline, EnvelopeMode, VisualizerMode};
+
+/// Settings for [`Visualizer::from_config`](crate::Visualizer::from_config).
+/// The default matches [`Visualizer::new`](crate::Visualizer::new) in waveform
+/// mode; each method changes one setting and hands the config back.
+///
+/// ```
+/// use echoes::{Theme, Visualizer, VisualizerConfig, VisualizerMode};
+///
+/// let config = VisualizerConfig::default()
+///     .mode(VisualizerMode::Spectrum)
+///     .bars(128)
+///     .theme(Theme::Fire);
+/// let visualizer = Visualizer::from_config(config);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct VisualizerConfig {
+    pub(crate) mode: VisualizerMode,
+    pub(crate) bars: Option<usize>,
+    pub(crate) height: Option<usize>,
+    pub(crate) theme: Theme,
+    pub(crate) color_depth: ColorDepth,
+    pub(crate) brightness: f32,
+    pub(crate) window: WindowFunction,
+    pub(crate) weighting: Weighting,
+    pub(crate) resample: bool,
+    pub(crate) normalization: Normalization,
+    pub(crate) gain: Option<f32>,
+    pub(crate) level_mode: LevelMode,
+    pub(crate) gate: f32,
+    pub(crate) db_floor: Option<f32>,
+    pub(crate) gamma: f32,
+    pub(crate) decay: f32,
+    pub(crate) smoothing: f32,
+    pub(crate) smoothing_time: Option<Duration>,
+    pub(crate) envelope: EnvelopeMode,
+    pub(crate) attack: Duration,
+    pub(crate) release: Duration,
+    pub(crate) peak_hold: bool,
+    pub(crate) beat_flash: bool,
+    pub(crate) readout: bool,
+    pub(crate) freq_labels: bool,
+    pub(crate) partial_blocks: bool,
+    pub(crate) smooth_edges: bool,
+    pub(crate) braille: bool,
+    pub(crate) centerline: Centerline,
+    pub(crate) upward: bool,
+    pub(crate) flip: bool,
+    pub(crate) ascii: bool,
+}
+
+impl Default for VisualizerConfig {
+    fn default() -> Self {
+        Self {
+            mode: VisualizerMode::default(),
+            bars: None,
+            height: None,
+            theme: Theme::default(),
+            color_depth: ColorDepth::Ansi256,
+            brightness: 1.0,
+            window: WindowFunction::default(),
+            weighting: Weighting::default(),
+            resample: true,
+            normalization: Normalization::default(),
+            gain: None,
+            level_mode: LevelMode::default(),
+            gate: -60.0,
+            db_floor: None,
+            gamma: 1.0,
+            decay: 0.92,
+            smoothing: 0.65,
+            smoothing_time: None,
+            envelope: EnvelopeMode::default(),
+            attack: Duration::from_millis(10),
+            release: Duration::from_millis(300),
+            peak_hold: true,
+            beat_flash: false,
+            readout: false,
+            freq_labels: false,
+            partial_blocks: true,
+            smooth_edges: false,
+            braille: false,
+            centerline: Centerline::default(),
+            upward: false,
+            flip: false,
+            ascii: false,
+        }
+    }
+}
+
+impl VisualizerConfig {
+    /// See [`Visualizer::new`](crate::Visualizer::new).
+    pub fn mode(mut self, mode: VisualizerMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// A fixed number of bars; by default they follow the terminal width.
+    pub fn bars(mut self, bars: usize) -> Self {
+        self.bars = Some(bars);
+        self
+    }
+
+    /// A fixed number of rows, header and progress line included; by default
+    /// the frame follows the terminal height.
+    pub fn height(mut self, rows: usize) -> Self {
+        self.height = Some(rows);
+        self
+    }
+
+    /// See [`Visualizer::with_theme`](crate::Visualizer::with_theme).
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// See [`Visualizer::with_color_depth`](crate::Visualizer::with_color_depth).
+    pub fn color_depth(mut self, color_depth: ColorDepth) -> Self {
+        self.color_depth = color_depth;
+        self
+    }
+
+    /// See [`Visualizer::with_brightness`](crate::Visualizer::with_brightness).
+    pub fn brightness(mut self, brightness: f32) -> Self {
+        self.brightness = brightness;
+        self
+    }
+
+    /// See [`Visualizer::with_window`](crate::Visualizer::with_window).
+    pub fn window(mut self, window: WindowFunction) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// See [`Visualizer::with_weighting`](crate::Visualizer::with_weighting).
+    pub fn weighting(mut self, weighting: Weighting) -> Self {
+        self.weighting = weighting;
+        self
+    }
+
+    /// See [`Visualizer::with_resample`](crate::Visualizer::with_resample).
+    pub fn resample(mut self, resample: bool) -> Self {
+        self.resample = resample;
+        self
+    }
+
+    /// See [`Visualizer::with_normalization`](crate::Visualizer::with_normalization).
+    pub fn normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// See [`Visualizer::with_gain`](crate::Visualizer::with_gain).
+    pub fn gain(mut self, gain: f32) -> Self {
+        self.gain = Some(gain);
+        self
+    }
+
+    /// See [`Visualizer::with_level_mode`](crate::Visualizer::with_level_mode).
+    pub fn level_mode(mut self, mode: LevelMode) -> Self {
+        self.level_mode = mode;
+        self
+    }
+
+    /// See [`Visualizer::with_gate`](crate::Visualizer::with_gate).
+    pub fn gate(mut self, dbfs: f32) -> Self {
+        self.gate = dbfs;
+        self
+    }
+
+    /// Decibel-scaled bars down to `floor`, or linear ones for `None`. See
+    /// [`Visualizer::with_db`](crate::Visualizer::with_db).
+    pub fn db_floor(mut self, floor: Option<f32>) -> Self {
+        self.db_floor = floor;
+        self
+    }
+
+    /// See [`Visualizer::with_gamma`](crate::Visualizer::with_gamma).
+    pub fn gamma(mut self, gamma: f32) -> Self {
+        self.gamma = gamma;
+        self
+    }
+
+    /// See [`Visualizer::with_decay`](crate::Visualizer::with_decay).
+    pub fn decay(mut self, decay: f32) -> Self {
+        self.decay = decay;
+        self
+    }
+
+    /// See [`Visualizer::with_smoothing`](crate::Visualizer::with_smoothing).
+    /// Drops any [`smoothing_time`](Self::smoothing_time) set before, which
+    /// would otherwise take its place.
+    pub fn smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing;
+        self.smoothing_time = None;
+        self
+    }
+
+    /// See [`Visualizer::with_smoothing_time`](crate::Visualizer::with_smoothing_time).
+    pub fn smoothing_time(mut self, time: Duration) -> Self {
+        self.smoothing_time = Some(time);
+        self
+    }
+
+    /// See [`Visualizer::with_envelope`](crate::Visualizer::with_envelope).
+    pub fn envelope(mut self, mode: EnvelopeMode) -> Self {
+        self.envelope = mode;
+        self
+    }
+
+    /// See [`Visualizer::with_attack`](crate::Visualizer::with_attack).
+    pub fn attack(mut self, attack: Duration) -> Self {
+        self.attack = attack;
+        self
+    }
+
+    /// See [`Visualizer::with_release`](crate::Visualizer::with_release).
+    pub fn release(mut self, release: Duration) -> Self {
+        self.release = release;
+        self
+    }
+
+    /// See [`Visualizer::with_peak_hold`](crate::Visualizer::with_peak_hold).
+    pub fn peak_hold(mut self, show: bool) -> Self {
+        self.peak_hold = show;
+        self
+    }
+
+    /// See [`Visualizer::with_beat_flash`](crate::Visualizer::with_beat_flash).
+    pub fn beat_flash(mut self, beat_flash: bool) -> Self {
+        self.beat_flash = beat_flash;
+        self
+    }
+
+    /// See [`Visualizer::with_readout`](crate::Visualizer::with_readout).
+    pub fn readout(mut self, readout: bool) -> Self {
+        self.readout = readout;
+        self
+    }
+
+    /// See [`Visualizer::with_freq_labels`](crate::Visualizer::with_freq_labels).
+    pub fn freq_labels(mut self, freq_labels: bool) -> Self {
+        self.freq_labels = freq_labels;
+        self
+    }
+
+    /// See [`Visualizer::with_partial_blocks`](crate::Visualizer::with_partial_blocks).
+    pub fn partial_blocks(mut self, partial_blocks: bool) -> Self {
+        self.partial_blocks = partial_blocks;
+        self
+    }
+
+    /// See [`Visualizer::with_smooth_edges`](crate::Visualizer::with_smooth_edges).
+    pub fn smooth_edges(mut self, smooth_edges: bool) -> Self {
+        self.smooth_edges = smooth_edges;
+        self
+    }
+
+    /// See [`Visualizer::with_braille`](crate::Visualizer::with_braille).
+    pub fn braille(mut self, braille: bool) -> Self {
+        self.braille = braille;
+        self
+    }
+
+    /// See [`Visualizer::with_centerline`](crate::Visualizer::with_centerline).
+    pub fn centerline(mut self, centerline: Centerline) -> Self {
+        self.centerline = centerline;
+        self
+    }
+
+    /// See [`Visualizer::with_upward`](crate::Visualizer::with_upward).
+    pub fn upward(mut self, upward: bool) -> Self {
+        self.upward = upward;
+        self
+    }
+
+    /// See [`Visualizer::with_flip`](crate::Visualizer::with_flip).
+    pub fn flip(mut self, flip: bool) -> Self {
+        self.flip = flip;
+        self
+    }
+
+    /// See [`Visualizer::with_ascii`](crate::Visualizer::with_ascii).
+    pub fn ascii(mut self, ascii: bool) -> Self {
+        self.ascii = ascii;
+        self
+    }
+}
+
+/// A line of a config file that couldn't be understood, as reported by the
+/// TOML parser.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigError {
+    /// 1-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// The keys a config file may set, each checked as it's read so that a bad value
+// is reported at its own line.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(deserialize_with = "choice")]
+    mode: Option<VisualizerMode>,
+    #[serde(deserialize_with = "count")]
+    bars: Option<usize>,
+    #[serde(deserialize_with = "count")]
+    height: Option<usize>,
+    #[serde(deserialize_with = "choice")]
+    theme: Option<Theme>,
+    #[serde(deserialize_with = "choice")]
+    color_depth: Option<ColorDepth>,
+    #[serde(deserialize_with = "fraction")]
+    brightness: Option<f32>,
+    #[serde(deserialize_with = "choice")]
+    window: Option<WindowFunction>,
+    #[serde(deserialize_with = "choice")]
+    weighting: Option<Weighting>,
+    resample: Option<bool>,
+    #[serde(deserialize_with = "choice")]
+    normalization: Option<Normalization>,
+    #[serde(deserialize_with = "choice")]
+    level_mode: Option<LevelMode>,
+    #[serde(deserialize_with = "positive")]
+    gain: Option<f32>,
+    #[serde(deserialize_with = "dbfs")]
+    gate: Option<f32>,
+    #[serde(deserialize_with = "db_floor")]
+    db_floor: Option<f32>,
+    #[serde(deserialize_with = "positive")]
+    gamma: Option<f32>,
+    #[serde(deserialize_with = "fraction")]
+    decay: Option<f32>,
+    #[serde(deserialize_with = "fraction")]
+    smoothing: Option<f32>,
+    #[serde(deserialize_with = "millis")]
+    smoothing_ms: Option<Duration>,
+    #[serde(deserialize_with = "choice")]
+    envelope: Option<EnvelopeMode>,
+    #[serde(deserialize_with = "millis")]
+    attack: Option<Duration>,
+    #[serde(deserialize_with = "millis")]
+    release: Option<Duration>,
+    peak_hold: Option<bool>,
+    beat_flash: Option<bool>,
+    readout: Option<bool>,
+    freq_labels: Option<bool>,
+    partial_blocks: Option<bool>,
+    smooth_edges: Option<bool>,
+    braille: Option<bool>,
+    #[serde(deserialize_with = "centerline")]
+    centerline: Option<Centerline>,
+    upward: Option<bool>,
+    flip: Option<bool>,
+    ascii: Option<bool>,
+}
+
+impl VisualizerConfig {
+    /// Replace the settings named in `text`, a TOML document of top-level
+    /// `key = value` lines, and keep the rest. Keys are the names of the
+    /// methods above (`bars = 128`, `theme = "fire"`, `peak_hold = false`);
+    /// `db_floor = -50` turns on decibel scaling and `smoothing_ms = 50` sets
+    /// [`smoothing_time`](Self::smoothing_time).
+    pub fn apply_toml(mut self, text: &str) -> Result<Self, ConfigError> {
+        let file: ConfigFile = toml::from_str(text).map_err(|err| ConfigError {
+            line: err
+                .span()
+                .map_or(1, |span| text[..span.start].matches('\n').count() + 1),
+            message: err.message().trim_end().to_string(),
+        })?;
+
+        let ConfigFile {
+            mode,
+            bars,
+            height,
+            theme,
+            color_depth,
+            brightness,
+            window,
+            weighting,
+            resample,
+            normalization,
+            level_mode,
+            gain,
+            gate,
+            db_floor,
+            gamma,
+            decay,
+            smoothing,
+            smoothing_ms,
+            envelope,
+            attack,
+            release,
+            peak_hold,
+            beat_flash,
+            readout,
+            freq_labels,
+            partial_blocks,
+            smooth_edges,
+            braille,
+            centerline,
+            upward,
+            flip,
+            ascii,
+        } = file;
+        fn set<T>(setting: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *setting = value;
+            }
+        }
+        set(&mut self.mode, mode);
+        set(&mut self.bars, bars.map(Some));
+        set(&mut self.height, height.map(Some));
+        set(&mut self.theme, theme);
+        set(&mut self.color_depth, color_depth);
+        set(&mut self.brightness, brightness);
+        set(&mut self.window, window);
+        set(&mut self.weighting, weighting);
+        set(&mut self.resample, resample);
+        set(&mut self.normalization, normalization);
+        set(&mut self.level_mode, level_mode);
+        set(&mut self.gain, gain.map(Some));
+        set(&mut self.gate, gate);
+        set(&mut self.db_floor, db_floor.map(Some));
+        set(&mut self.gamma, gamma);
+        set(&mut self.decay, decay);
+        set(&mut self.smoothing, smoothing);
+        set(&mut self.smoothing_time, smoothing_ms.map(Some));
+        set(&mut self.envelope, envelope);
+        set(&mut self.attack, attack);
+        set(&mut self.release, release);
+        set(&mut self.peak_hold, peak_hold);
+        set(&mut self.beat_flash, beat_flash);
+        set(&mut self.readout, readout);
+        set(&mut self.freq_labels, freq_labels);
+        set(&mut self.partial_blocks, partial_blocks);
+        set(&mut self.smooth_edges, smooth_edges);
+        set(&mut self.braille, braille);
+        set(&mut self.centerline, centerline);
+        set(&mut self.upward, upward);
+        set(&mut self.flip, flip);
+        set(&mut self.ascii, ascii);
+        Ok(self)
+    }
+}
+
+// One of the names the command line takes for `T`.
+fn choice<'de, D: Deserializer<'de>, T: ValueEnum>(deserializer: D) -> Result<Option<T>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    T::from_str(&name, true).map(Some).map_err(|_| {
+        let names: Vec<_> = T::value_variants()
+            .iter()
+            .filter_map(|variant| variant.to_possible_value())
+            .map(|possible| possible.get_name().to_string())
+            .collect();
+        D::Error::custom(format!("`{}` isn't one of {}", name, names.join(", ")))
+    })
+}
+
+fn count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    match i64::deserialize(deserializer)? {
+        n @ 1..=65535 => Ok(Some(n as usize)),
+        _ => Err(D::Error::custom("must be a whole number from 1 to 65535")),
+    }
+}
+
+fn number<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    valid: impl Fn(f32) -> bool,
+    requirement: &str,
+) -> Result<Option<f32>, D::Error> {
+    let n = f32::deserialize(deserializer)?;
+    if n.is_finite() && valid(n) {
+        Ok(Some(n))
+    } else {
+        Err(D::Error::custom(requirement))
+    }
+}
+
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    number(deserializer, |n| n > 0.0, "must be greater than 0")
+}
+
+fn dbfs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    number(deserializer, |dbfs| dbfs <= 0.0, "must be 0 or lower")
+}
+
+fn db_floor<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    number(deserializer, |floor| floor <= -1.0, "must be -1 or lower")
+}
+
+fn fraction<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    number(
+        deserializer,
+        |n| (0.0..=1.0).contains(&n),
+        "must be between 0.0 and 1.0",
+    )
+}
+
+fn millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let ms = number(deserializer, |ms| ms >= 0.0, "must be 0 or more")?;
+    Ok(ms.map(|ms| Duration::from_secs_f32(ms / 1000.0)))
+}
+
+fn centerline<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Centerline>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    text.parse().map(Some).map_err(D::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_in_the_file_replace_the_defaults() {
+        let config = VisualizerConfig::default()
+            .apply_toml(
+                "# my setup\n\
+                 mode = \"spectrum\"\n\
+                 bars = 128   # wide\n\
+                 theme = 'fire'\n\
+                 \n\
+                 decay = 0.7\n\
+                 peak_hold = false\n\
+                 db_floor = -50\n",
+            )
+            .unwrap();
+
+        let expected = VisualizerConfig::default()
+            .mode(VisualizerMode::Spectrum)
+            .bars(128)
+            .theme(Theme::Fire)
+            .decay(0.7)
+            .peak_hold(false)
+            .db_floor(Some(-50.0));
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn later_settings_replace_those_from_the_file() {
+        // As the command line's `--smoothing` does after a config file.
+        let config = VisualizerConfig::default()
+            .apply_toml("smoothing_ms = 50\ndecay = 0.7")
+            .unwrap()
+            .smoothing(0.5)
+            .decay(0.8);
+        let expected = VisualizerConfig::default().smoothing(0.5).decay(0.8);
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn bad_lines_are_reported_with_their_number() {
+        let error = |text| VisualizerConfig::default().apply_toml(text).unwrap_err();
+
+        assert_eq!(error("bars = 64\nbras = 64").line, 2);
+        assert!(error("theme = \"lava\"").message.contains("fire"));
+        assert_eq!(error("smoothing = 2").line, 1);
+        assert_eq!(error("bars = 64\n\nsmoothing = 2").line, 3);
+        assert_eq!(error("bars = 1.5").line, 1);
+        assert_eq!(error("[colors]").line, 1);
+    }
+}
@@ -0,0 +1,1088 @@
+//! Opening files and driving the visualizer from decoded packets.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use log::{debug, warn};
+use symphonia::core::audio::{AudioBuffer, AudioBufferRef, Channels, Signal};
+#[cfg(feature = "audio")]
+use symphonia::core::codecs::CodecParameters;
+use symphonia::core::codecs::Decoder;
+use symphonia::core::errors::{Error as SymphoniaError, SeekErrorKind};
+use symphonia::core::formats::{FormatReader, SeekMode, SeekTo, Track};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::{StandardTagKey, Tag};
+use symphonia::core::sample::Sample;
+use symphonia::core::units::{Time, TimeBase};
+use symphonia::default::{get_codecs, get_probe};
+
+#[cfg(feature = "audio")]
+use crate::audio;
+use crate::interrupted;
+use crate::terminal::{Command, read_commands};
+use crate::visualizer::Visualizer;
+
+// How far the arrow keys jump, in seconds.
+const SEEK_STEP: f64 = 5.0;
+
+/// Everything that can go wrong while opening or decoding a file.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The file couldn't be opened.
+    Open(io::Error),
+    /// Reading input or writing frames failed.
+    Io(io::Error),
+    /// The file isn't audio symphonia can decode, or is corrupt.
+    Symphonia(SymphoniaError),
+    /// The file has no audio track to play.
+    NoDefaultTrack,
+    /// Every entry of a playlist was skipped.
+    NothingToPlay,
+    /// The requested start time is past the end of the track.
+    StartPastEnd,
+    /// Live audio couldn't be captured, as on a platform without loopback.
+    Capture(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Open(err) | DecodeError::Io(err) => write!(f, "{}", err),
+            DecodeError::Symphonia(err) => write!(f, "{}", err),
+            DecodeError::NoDefaultTrack => write!(f, "no default track in file"),
+            DecodeError::NothingToPlay => write!(f, "nothing playable"),
+            DecodeError::StartPastEnd => write!(f, "start time is past the end of the track"),
+            DecodeError::Capture(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodeError::Open(err) | DecodeError::Io(err) => Some(err),
+            DecodeError::Symphonia(err) => Some(err),
+            DecodeError::NoDefaultTrack
+            | DecodeError::NothingToPlay
+            | DecodeError::StartPastEnd
+            | DecodeError::Capture(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for DecodeError {
+    fn from(err: io::Error) -> Self {
+        DecodeError::Io(err)
+    }
+}
+
+impl From<SymphoniaError> for DecodeError {
+    fn from(err: SymphoniaError) -> Self {
+        DecodeError::Symphonia(err)
+    }
+}
+
+/// Playback settings that aren't about drawing.
+pub struct PlayOptions {
+    /// Send decoded audio to the speakers as well as the visualizer.
+    pub audio: bool,
+    pub channel_mode: ChannelMode,
+    /// How channels are combined in [`ChannelMode::Mono`].
+    pub mono_mix: MonoMix,
+    /// Draw only this channel, counting from 0, instead of mixing them by
+    /// `channel_mode`. A channel the track doesn't have falls back to 0.
+    pub channel: Option<usize>,
+    /// Read key presses from the terminal between packets.
+    pub controls: bool,
+    /// Start each file over when it ends, until interrupted or quit.
+    pub looping: bool,
+    /// Run each track of a playlist straight into the next: the following file
+    /// is opened while this one plays, and the audio output and the
+    /// visualizer's levels carry on across the join rather than starting over.
+    pub gapless: bool,
+    /// Seek this far into each file before drawing anything.
+    pub start: Option<Duration>,
+    /// Stop each file once this much of it has played, counting from its
+    /// beginning rather than from `start`.
+    pub end: Option<Duration>,
+    /// Stop playing at this moment, whatever track is playing and however far
+    /// into it, and play nothing after it.
+    pub stop_at: Option<Instant>,
+    /// Draw at most this many frames per second of audio. Buffers that arrive
+    /// sooner are held back and drawn along with the next frame. `None` draws
+    /// a frame for every decoded buffer.
+    pub fps: Option<u32>,
+    /// Analyze fixed windows of this many samples instead of whatever each
+    /// decoded buffer holds, so frames cover the same stretch of audio with
+    /// every codec. Samples wait until a window is full; a buffer that fills
+    /// several draws a frame for each, timed by the window's last sample and,
+    /// with `realtime`, held until that sample plays. `None` draws each
+    /// buffer as it comes.
+    pub window: Option<usize>,
+    /// With `window`, start each window this many samples after the last, so
+    /// windows overlap when it's less than the window. Defaults to the window,
+    /// and is held to it.
+    pub hop: Option<usize>,
+    /// Hold each frame until its audio is due. Off, files are rendered as fast
+    /// as they decode, as when writing frames to images.
+    pub realtime: bool,
+}
+
+/// How playback of a file ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Reached the end of the file, or was interrupted.
+    Finished,
+    /// The listener pressed `q`; don't move on to the next file.
+    Quit,
+    /// [`PlayOptions::stop_at`] came; don't move on either.
+    TimeUp,
+}
+
+/// Decode an audio file, rendering a frame into `out` for every packet at
+/// playback speed. A `path` of `-` reads from stdin instead, in any format that
+/// can be probed without seeking. Reaching the end of the file finishes it;
+/// reading the file failing partway is [`DecodeError::Symphonia`], and
+/// failures writing to `out` come back as [`DecodeError::Io`].
+pub fn decode_file<W: Write>(
+    path: &Path,
+    visualizer: &mut Visualizer,
+    options: &PlayOptions,
+    out: &mut W,
+) -> Result<Outcome, DecodeError> {
+    play_file(
+        open_file(path)?,
+        visualizer,
+        options,
+        &mut Carry::default(),
+        out,
+    )
+}
+
+// A file probed and ready to decode, with the tags found on the way.
+pub(crate) struct OpenFile {
+    path: PathBuf,
+    format: Box<dyn FormatReader>,
+    tags: Vec<Tag>,
+}
+
+// What one track hands on to the next when playing gaplessly: the audio output,
+// still playing the last buffers it was given, and samples not drawn yet.
+#[derive(Default)]
+pub(crate) struct Carry {
+    // The output and the rate and layout it was opened for.
+    #[cfg(feature = "audio")]
+    output: Option<(audio::AudioOutput, Option<u32>, Option<Channels>)>,
+    pending: [Vec<f32>; 2],
+}
+
+// Open and probe `path`, reading no further than its headers and tags.
+pub(crate) fn open_file(path: &Path) -> Result<OpenFile, DecodeError> {
+    let src: Box<dyn MediaSource> = if path == Path::new("-") {
+        Box::new(StdinSource(io::stdin()))
+    } else {
+        Box::new(File::open(path).map_err(DecodeError::Open)?)
+    };
+    probe(path, src)
+}
+
+// Probe `src`, read from `path`, for its format and tags.
+fn probe(path: &Path, src: Box<dyn MediaSource>) -> Result<OpenFile, DecodeError> {
+    let mss = MediaSourceStream::new(src, Default::default());
+
+    let mut probe = get_probe()
+        .format(
+            &Default::default(),
+            mss,
+            &Default::default(),
+            &Default::default(),
+        )
+        .inspect_err(|err| debug!("{}: probe failed: {}", path.display(), err))?;
+
+    let mut format = probe.format;
+
+    // Tags live in the stream for most formats, but ahead of it for ID3v2 on MP3.
+    let tags = format
+        .metadata()
+        .current()
+        .map(|revision| revision.tags().to_vec())
+        .or_else(|| {
+            let metadata = probe.metadata.get()?;
+            metadata.current().map(|revision| revision.tags().to_vec())
+        })
+        .unwrap_or_default();
+
+    Ok(OpenFile {
+        path: path.to_path_buf(),
+        format,
+        tags,
+    })
+}
+
+// Play `file` as `decode_file` does, taking over whatever `carry` holds from
+// the track before and, with `options.gapless`, leaving it what this one does.
+pub(crate) fn play_file<W: Write>(
+    file: OpenFile,
+    visualizer: &mut Visualizer,
+    options: &PlayOptions,
+    carry: &mut Carry,
+    out: &mut W,
+) -> Result<Outcome, DecodeError> {
+    let OpenFile {
+        path,
+        mut format,
+        tags,
+    } = file;
+    let path = path.as_path();
+    if !options.gapless {
+        visualizer.reset();
+    }
+    visualizer.set_header(Some(track_header(&tags, path)));
+
+    let track = format.default_track().ok_or(DecodeError::NoDefaultTrack)?;
+    let mut decoder = make_decoder(path, track)?;
+    let mut track_rate = track.codec_params.sample_rate;
+    let mut track_id = track.id;
+    let mut time_base = track.codec_params.time_base;
+    let mut total_frames = track.codec_params.n_frames;
+
+    // An output left playing by the last track is kept if it fits this one.
+    #[cfg(feature = "audio")]
+    let mut output = match carry.output.take() {
+        Some((output, rate, channels))
+            if (rate, channels) == (track_rate, track.codec_params.channels) =>
+        {
+            Some(output)
+        }
+        _ if options.audio => open_output(&track.codec_params),
+        _ => None,
+    };
+    // Built without playback support: nothing to send the audio to.
+    #[cfg(not(feature = "audio"))]
+    let _ = options.audio;
+
+    // Pace frames against the wall clock so drift doesn't build up across packets.
+    let mut started = Instant::now();
+    let mut played_frames = 0u64;
+    let mut paused = false;
+    let mut logged_format = false;
+    // Packets dropped for being corrupt, reported once the file is done.
+    let mut skipped_packets = 0u64;
+    // Samples not drawn yet (left and right, or mono in the first), and the
+    // playing time at which the next frame is due with a frame rate cap.
+    let mut pending = std::mem::take(&mut carry.pending);
+    let mut next_frame = Duration::ZERO;
+    let frame_interval = options
+        .fps
+        .map(|fps| Duration::from_secs_f64(1.0 / fps.max(1) as f64));
+    // Where `--start` put the track, which is also where looping goes back to,
+    // and whether `end` has been reached.
+    let mut start = options.start.unwrap_or_default().as_secs_f64();
+    let mut first_frame = 0u64;
+    let mut past_end = false;
+
+    if let Some(rate) = track_rate.filter(|&rate| rate > 0) {
+        let duration = total_frames.map(|total| total as f64 / rate as f64);
+        if duration.is_some_and(|duration| start >= duration) {
+            return Err(DecodeError::StartPastEnd);
+        }
+        if let (Some(end), Some(duration)) = (options.end, duration)
+            && end.as_secs_f64() > duration
+        {
+            warn!(
+                "{}: the track is only {:.1}s long; playing to the end",
+                path.display(),
+                duration
+            );
+        }
+        if start > 0.0 {
+            match seek(format.as_mut(), start, track_id, time_base, rate) {
+                Ok(seconds) => {
+                    first_frame = (seconds * rate as f64) as u64;
+                    played_frames = first_frame;
+                    started = Instant::now()
+                        .checked_sub(Duration::from_secs_f64(seconds))
+                        .unwrap_or_else(Instant::now);
+                }
+                Err(err) => warn!("{}: can't seek to the start time: {}", path.display(), err),
+            }
+        }
+    } else if start > 0.0 {
+        warn!(
+            "{}: unknown sample rate; playing from the beginning",
+            path.display()
+        );
+        start = 0.0;
+    }
+
+    loop {
+        // While paused nothing is decoded, so spin here on the keyboard alone.
+        let time_up = || options.stop_at.is_some_and(|at| Instant::now() >= at);
+        while options.controls && !interrupted() && !time_up() {
+            for command in read_commands() {
+                match command {
+                    Command::TogglePause => {
+                        paused = !paused;
+                        #[cfg(feature = "audio")]
+                        if let Some(output) = output.as_ref() {
+                            output.set_paused(paused);
+                        }
+                    }
+                    Command::NextMode => visualizer.set_mode(visualizer.mode().next()),
+                    Command::SeekBackward | Command::SeekForward => {
+                        // Seeking needs a known rate to turn frames into seconds.
+                        let Some(rate) = track_rate.filter(|&rate| rate > 0) else {
+                            continue;
+                        };
+                        let step = match command {
+                            Command::SeekBackward => -SEEK_STEP,
+                            _ => SEEK_STEP,
+                        };
+                        let target = (played_frames as f64 / rate as f64 + step).max(0.0);
+                        if total_frames.is_some_and(|total| target * rate as f64 >= total as f64) {
+                            return Ok(Outcome::Finished);
+                        }
+
+                        match seek(format.as_mut(), target, track_id, time_base, rate) {
+                            Ok(seconds) => {
+                                past_end = false;
+                                played_frames = (seconds * rate as f64) as u64;
+                                started = Instant::now()
+                                    .checked_sub(Duration::from_secs_f64(seconds))
+                                    .unwrap_or_else(Instant::now);
+                                decoder.reset();
+                                visualizer.clear_levels();
+                                pending.iter_mut().for_each(Vec::clear);
+                                next_frame = Duration::ZERO;
+                                #[cfg(feature = "audio")]
+                                if let Some(output) = output.as_ref() {
+                                    output.clear();
+                                }
+                            }
+                            Err(SymphoniaError::SeekError(SeekErrorKind::OutOfRange)) => {
+                                return Ok(Outcome::Finished);
+                            }
+                            // Unseekable sources like stdin just keep playing.
+                            Err(err) => debug!("{}: seek failed: {}", path.display(), err),
+                        }
+                    }
+                    Command::Quit => return Ok(Outcome::Quit),
+                }
+            }
+
+            if !paused {
+                break;
+            }
+            // Push the schedule back so resuming doesn't rush to catch up.
+            let nap = Duration::from_millis(20);
+            std::thread::sleep(nap);
+            started += nap;
+        }
+
+        if interrupted() {
+            break;
+        }
+        if time_up() {
+            return Ok(Outcome::TimeUp);
+        }
+        let packet = match format.next_packet() {
+            Ok(packet) if !past_end => packet,
+            // The stream changed shape, as between chained segments: carry on
+            // with a decoder for the track that follows.
+            Err(SymphoniaError::ResetRequired) if !past_end => {
+                let track = format.default_track().ok_or(DecodeError::NoDefaultTrack)?;
+                let params = &track.codec_params;
+                #[cfg(feature = "audio")]
+                if options.audio
+                    && (params.sample_rate != track_rate
+                        || params.channels != decoder.codec_params().channels)
+                {
+                    output = open_output(params);
+                }
+
+                decoder = make_decoder(path, track)?;
+                track_rate = params.sample_rate;
+                track_id = track.id;
+                time_base = params.time_base;
+                // Time keeps counting across segments, so the new one ends that much later.
+                total_frames = params.n_frames.map(|frames| played_frames + frames);
+                continue;
+            }
+            // A read failing is an error, not the track ending early.
+            Err(err) if !past_end && !end_of_stream(&err) => return Err(err.into()),
+            // Starting over needs a seekable source that produced something last time.
+            _ if options.looping && played_frames > first_frame => {
+                let rate = track_rate.unwrap_or(0);
+                let seconds = match seek(format.as_mut(), start, track_id, time_base, rate.max(1)) {
+                    Ok(seconds) => seconds,
+                    Err(err) => {
+                        warn!("{}: can't loop: {}", path.display(), err);
+                        break;
+                    }
+                };
+                debug!("{}: looping", path.display());
+                decoder.reset();
+                pending.iter_mut().for_each(Vec::clear);
+                next_frame = Duration::ZERO;
+                past_end = false;
+                played_frames = (seconds * rate as f64) as u64;
+                first_frame = played_frames;
+                started = Instant::now()
+                    .checked_sub(Duration::from_secs_f64(seconds))
+                    .unwrap_or_else(Instant::now);
+                continue;
+            }
+            _ => break,
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoding = Instant::now();
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::ResetRequired) => {
+                debug!("{}: decoder reset", path.display());
+                decoder.reset();
+                continue;
+            }
+            // A damaged packet; the ones after it usually decode fine.
+            Err(SymphoniaError::DecodeError(err)) => {
+                debug!("{}: skipping packet: {}", path.display(), err);
+                skipped_packets += 1;
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        visualizer.add_decode_time(decoding.elapsed());
+        if !logged_format {
+            debug!(
+                "{}: decoding {} samples",
+                path.display(),
+                sample_format(&decoded)
+            );
+            logged_format = true;
+
+            let channels = decoded.spec().channels.count();
+            if let Some(channel) = options.channel.filter(|&channel| channel >= channels) {
+                warn!(
+                    "{}: no channel {} in a {}-channel track; showing channel 0",
+                    path.display(),
+                    channel,
+                    channels
+                );
+            }
+        }
+        let sample_rate = track_rate.unwrap_or(decoded.spec().rate).max(1);
+        let to_time = |frames: u64| Duration::from_secs_f64(frames as f64 / sample_rate as f64);
+        played_frames += decoded.frames() as u64;
+        let elapsed = to_time(played_frames);
+        let total = total_frames.map(to_time);
+        visualizer.set_sample_rate(sample_rate);
+        visualizer.set_progress(elapsed, total);
+
+        // With sound, the device is the clock: wait for it to finish what came
+        // before this buffer and then draw, so frames stay on what's heard
+        // however far the device drifts from the wall clock. Without, frames
+        // are paced by sleeping on the wall clock after drawing.
+        #[cfg(feature = "audio")]
+        let device_clock = match output.as_mut() {
+            Some(output) => {
+                output.push(decoded.clone());
+                output.wait_until_queued(decoded.frames());
+                true
+            }
+            None => false,
+        };
+        #[cfg(not(feature = "audio"))]
+        let device_clock = false;
+        // When the start of this buffer is heard, for timing what's within it.
+        let buffer_start = to_time(played_frames - decoded.frames() as u64);
+        let buffer_heard = if device_clock {
+            Instant::now()
+        } else {
+            started + buffer_start
+        };
+
+        match (options.channel, options.channel_mode) {
+            (Some(channel), _) => pending[0].extend(to_channel_f32(&decoded, channel)),
+            (None, ChannelMode::Mono) => pending[0].extend(to_mono_f32(&decoded, options.mono_mix)),
+            (None, ChannelMode::Stereo) => {
+                let (left, right) = to_stereo_f32(&decoded);
+                pending[0].extend(left);
+                pending[1].extend(right);
+            }
+        }
+        match options.window {
+            None => {
+                if frame_due(elapsed, &mut next_frame, frame_interval) {
+                    draw_frame(visualizer, out, options, &pending[0], &pending[1])?;
+                    pending.iter_mut().for_each(Vec::clear);
+                }
+            }
+            // Every window the buffer completes, each at the time its last
+            // sample plays; with a frame rate cap, those that come before the
+            // next frame is due are passed over.
+            Some(window) => {
+                let window = window.max(1);
+                let hop = options.hop.unwrap_or(window).clamp(1, window);
+                while pending[0].len() >= window {
+                    let ahead = (pending[0].len() - window) as u64;
+                    let at = to_time(played_frames.saturating_sub(ahead));
+                    if frame_due(at, &mut next_frame, frame_interval) {
+                        if options.realtime {
+                            let due = buffer_heard + at.saturating_sub(buffer_start);
+                            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+                        }
+                        visualizer.set_progress(at, total);
+                        let right = &pending[1][..window.min(pending[1].len())];
+                        draw_frame(visualizer, out, options, &pending[0][..window], right)?;
+                    }
+                    for samples in &mut pending {
+                        samples.drain(..hop.min(samples.len()));
+                    }
+                }
+            }
+        }
+        past_end = options.end.is_some_and(|end| elapsed >= end);
+
+        if let Some(wait) = elapsed.checked_sub(started.elapsed())
+            && options.realtime
+            && !device_clock
+        {
+            std::thread::sleep(wait);
+        }
+    }
+
+    if skipped_packets > 0 {
+        warn!(
+            "{}: skipped {} undecodable packet{}",
+            path.display(),
+            skipped_packets,
+            if skipped_packets == 1 { "" } else { "s" }
+        );
+    }
+    if options.gapless {
+        #[cfg(feature = "audio")]
+        {
+            let channels = decoder.codec_params().channels;
+            carry.output = output.map(|output| (output, track_rate, channels));
+        }
+        carry.pending = pending;
+    }
+    Ok(Outcome::Finished)
+}
+
+// Whether a frame is due at `elapsed` into the track, moving `next_frame` on to
+// the one after it when there's a frame rate cap.
+fn frame_due(elapsed: Duration, next_frame: &mut Duration, interval: Option<Duration>) -> bool {
+    if elapsed < *next_frame {
+        return false;
+    }
+    if let Some(interval) = interval {
+        // The next slot on a fixed grid, so frame times don't drift.
+        let slots = (elapsed.as_secs_f64() / interval.as_secs_f64()).floor();
+        *next_frame = interval.mul_f64(slots + 1.0);
+    }
+    true
+}
+
+// Draw one frame of `left` alone, or beside `right` in stereo.
+fn draw_frame<W: Write>(
+    visualizer: &mut Visualizer,
+    out: &mut W,
+    options: &PlayOptions,
+    left: &[f32],
+    right: &[f32],
+) -> io::Result<()> {
+    match options.channel_mode {
+        ChannelMode::Stereo if options.channel.is_none() => {
+            visualizer.render_stereo(out, left, right)
+        }
+        _ => visualizer.render(out, left),
+    }
+}
+
+// Seek `format` to about `seconds` into the track, returning the time that was
+// actually reached.
+fn seek(
+    format: &mut dyn FormatReader,
+    seconds: f64,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    rate: u32,
+) -> Result<f64, SymphoniaError> {
+    let seeked = format.seek(
+        SeekMode::Coarse,
+        SeekTo::Time {
+            time: Time::from(seconds),
+            track_id: Some(track_id),
+        },
+    )?;
+    let time = time_base
+        .unwrap_or(TimeBase::new(1, rate))
+        .calc_time(seeked.actual_ts);
+    Ok(time.seconds as f64 + time.frac)
+}
+
+// Whether `err` is only the format reader running out of input at the end.
+fn end_of_stream(err: &SymphoniaError) -> bool {
+    matches!(err, SymphoniaError::IoError(err) if err.kind() == io::ErrorKind::UnexpectedEof)
+}
+
+// "3. Title - Artist - Album" from whichever tags are present, with the file
+// name standing in for a missing title.
+fn track_header(tags: &[Tag], path: &Path) -> String {
+    let find = |key| {
+        tags.iter()
+            .find(|tag| tag.std_key == Some(key))
+            .map(|tag| tag.value.to_string())
+            .filter(|value| !value.is_empty())
+    };
+
+    let title = find(StandardTagKey::TrackTitle).unwrap_or_else(|| match path.file_name() {
+        _ if path == Path::new("-") => "stdin".to_string(),
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => path.display().to_string(),
+    });
+    let mut header = match find(StandardTagKey::TrackNumber) {
+        Some(number) => format!("{}. {}", number, title),
+        None => title,
+    };
+    for part in [find(StandardTagKey::Artist), find(StandardTagKey::Album)]
+        .into_iter()
+        .flatten()
+    {
+        header.push_str(" - ");
+        header.push_str(&part);
+    }
+    header
+}
+
+// Stdin as a media source. It's a pipe, so symphonia is told not to seek.
+struct StdinSource(io::Stdin);
+
+impl Read for StdinSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Seek for StdinSource {
+    fn seek(&mut self, _: SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "can't seek in stdin",
+        ))
+    }
+}
+
+impl MediaSource for StdinSource {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Which channels of the source drive the bars.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ChannelMode {
+    /// Every channel mixed into one, as [`PlayOptions::mono_mix`] says.
+    #[default]
+    Mono,
+    /// Left channel on the left half, right channel on the right half.
+    Stereo,
+}
+
+/// How the channels are mixed down to one in [`ChannelMode::Mono`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MonoMix {
+    /// Every channel counted equally.
+    #[default]
+    Average,
+    /// Just the first channel.
+    Left,
+    /// Just the second channel, or the first of a mono track.
+    Right,
+    /// The mono sum of the ITU-R BS.775 stereo downmix: front left and right
+    /// at half, the surrounds at -3 dB against them, and the LFE left out. The
+    /// centre feeds both sides at -3 dB, so it sums to +3 dB against the fronts.
+    Itu,
+}
+
+// A decoder for `track`, logging what it is.
+fn make_decoder(path: &Path, track: &Track) -> Result<Box<dyn Decoder>, DecodeError> {
+    let params = &track.codec_params;
+    let codec = get_codecs().get_codec(params.codec);
+    debug!(
+        "{}: {} track, {} Hz, {} channels, {} frames",
+        path.display(),
+        codec.map_or("unknown", |codec| codec.short_name),
+        describe(params.sample_rate),
+        describe(params.channels.map(|channels| channels.count())),
+        describe(params.n_frames),
+    );
+    Ok(get_codecs().make(params, &Default::default())?)
+}
+
+// Name of the sample type symphonia decoded to.
+fn sample_format(decoded: &AudioBufferRef) -> &'static str {
+    match decoded {
+        AudioBufferRef::U8(_) => "u8",
+        AudioBufferRef::U16(_) => "u16",
+        AudioBufferRef::U24(_) => "u24",
+        AudioBufferRef::U32(_) => "u32",
+        AudioBufferRef::S8(_) => "s8",
+        AudioBufferRef::S16(_) => "s16",
+        AudioBufferRef::S24(_) => "s24",
+        AudioBufferRef::S32(_) => "s32",
+        AudioBufferRef::F32(_) => "f32",
+        AudioBufferRef::F64(_) => "f64",
+    }
+}
+
+// A codec parameter for logging, or "unknown" when the container doesn't say.
+fn describe<T: fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "unknown".to_string(), |value| value.to_string())
+}
+
+// The `channels` of `decoded`, as far as it has them, each as its own plane of
+// f32 samples in -1.0..1.0. Each integer format is scaled by its own full
+// scale, 2^(bits - 1), so a 24- or 32-bit file comes out as loud as a 16-bit
+// one. Symphonia keeps 24-bit samples in the low bits of `i24`, and shifts FLAC
+// up to fill `i32`.
+fn planes_f32(decoded: &AudioBufferRef, channels: Range<usize>) -> Vec<Vec<f32>> {
+    let channels = channels.start..channels.end.min(decoded.spec().channels.count());
+    match decoded {
+        AudioBufferRef::U8(buf) => planes(buf, channels, |x| (x as f32 - 128.0) / 128.0),
+        AudioBufferRef::U16(buf) => planes(buf, channels, |x| (x as f32 - 32_768.0) / 32_768.0),
+        AudioBufferRef::U24(buf) => planes(buf, channels, |x| {
+            (x.inner() as f32 - 8_388_608.0) / 8_388_608.0
+        }),
+        AudioBufferRef::U32(buf) => planes(buf, channels, |x| {
+            ((x as f64 - 2_147_483_648.0) / 2_147_483_648.0) as f32
+        }),
+        AudioBufferRef::S8(buf) => planes(buf, channels, |x| x as f32 / 128.0),
+        AudioBufferRef::S16(buf) => planes(buf, channels, |x| x as f32 / 32_768.0),
+        AudioBufferRef::S24(buf) => planes(buf, channels, |x| x.inner() as f32 / 8_388_608.0),
+        AudioBufferRef::S32(buf) => planes(buf, channels, |x| (x as f64 / 2_147_483_648.0) as f32),
+        AudioBufferRef::F32(buf) => planes(buf, channels, |x| x),
+        AudioBufferRef::F64(buf) => planes(buf, channels, |x| x as f32),
+    }
+}
+
+fn planes<S: Sample>(
+    buf: &AudioBuffer<S>,
+    channels: Range<usize>,
+    to_f32: impl Fn(S) -> f32,
+) -> Vec<Vec<f32>> {
+    channels
+        .map(|ch| buf.chan(ch).iter().map(|&x| to_f32(x)).collect())
+        .collect()
+}
+
+// The channels mixed into one mono buffer by `mix`. Symphonia buffers are
+// planar, so frame `f` sums `f` of each plane rather than neighbouring samples.
+fn to_mono_f32(decoded: &AudioBufferRef, mix: MonoMix) -> Vec<f32> {
+    match mix {
+        MonoMix::Left => return to_channel_f32(decoded, 0),
+        MonoMix::Right => return to_channel_f32(decoded, 1),
+        _ => {}
+    }
+    let planes = planes_f32(decoded, 0..decoded.spec().channels.count());
+    let weights: Vec<f32> = match mix {
+        // A single channel is already mono, whatever its position.
+        MonoMix::Itu if planes.len() > 1 => {
+            decoded.spec().channels.iter().map(itu_weight).collect()
+        }
+        _ => vec![1.0 / planes.len().max(1) as f32; planes.len()],
+    };
+    let mut mono = vec![0.0f32; decoded.frames()];
+
+    for (plane, &weight) in planes.iter().zip(&weights) {
+        for (acc, &sample) in mono.iter_mut().zip(plane) {
+            *acc += sample * weight;
+        }
+    }
+
+    mono
+}
+
+// How much of one speaker goes into the ITU mono downmix: half of each front
+// side, so a stereo track is averaged, the surrounds 3 dB down on that and the
+// centre, which goes to both sides 3 dB down, 3 dB up.
+fn itu_weight(channel: Channels) -> f32 {
+    const SIDE: f32 = 0.5;
+    if channel == Channels::FRONT_LEFT || channel == Channels::FRONT_RIGHT {
+        SIDE
+    } else if channel == Channels::FRONT_CENTRE {
+        2.0 * SIDE * std::f32::consts::FRAC_1_SQRT_2
+    } else if channel == Channels::LFE1 || channel == Channels::LFE2 {
+        0.0
+    } else {
+        SIDE * std::f32::consts::FRAC_1_SQRT_2
+    }
+}
+
+// One channel, or channel 0 if there's no such channel.
+fn to_channel_f32(decoded: &AudioBufferRef, channel: usize) -> Vec<f32> {
+    let count = decoded.spec().channels.count();
+    let channel = if channel < count { channel } else { 0 };
+    planes_f32(decoded, channel..channel + 1)
+        .pop()
+        .unwrap_or_default()
+}
+
+// Left and right channels; mono sources fill both sides.
+fn to_stereo_f32(decoded: &AudioBufferRef) -> (Vec<f32>, Vec<f32>) {
+    let mut planes = planes_f32(decoded, 0..2).into_iter();
+    let left = planes.next().unwrap_or_default();
+    let right = planes.next().unwrap_or_else(|| left.clone());
+    (left, right)
+}
+
+// Speaker output matching the track, or `None` (with a warning) if it can't be opened.
+#[cfg(feature = "audio")]
+fn open_output(params: &CodecParameters) -> Option<audio::AudioOutput> {
+    let (Some(rate), Some(channels)) = (params.sample_rate, params.channels) else {
+        warn!("playing silently: unknown sample rate or channel layout");
+        return None;
+    };
+
+    match audio::AudioOutput::open(rate, channels.count()) {
+        Ok(output) => {
+            debug!("audio output at {} Hz, {} channels", rate, channels.count());
+            Some(output)
+        }
+        Err(err) => {
+            warn!("playing silently: {}", err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symphonia::core::audio::SignalSpec;
+    use symphonia::core::meta::Value;
+
+    // A planar f32 buffer with one plane per entry of `planes`.
+    fn buffer(planes: &[&[f32]]) -> AudioBuffer<f32> {
+        let channels = [
+            Channels::FRONT_LEFT,
+            Channels::FRONT_RIGHT,
+            Channels::FRONT_CENTRE,
+            Channels::LFE1,
+            Channels::REAR_LEFT,
+            Channels::REAR_RIGHT,
+        ][..planes.len()]
+            .iter()
+            .fold(Channels::empty(), |all, &channel| all | channel);
+        let frames = planes[0].len();
+        let mut buf = AudioBuffer::new(frames as u64, SignalSpec::new(44_100, channels));
+        buf.render_reserved(Some(frames));
+        for (ch, plane) in planes.iter().enumerate() {
+            buf.chan_mut(ch).copy_from_slice(plane);
+        }
+        buf
+    }
+
+    #[test]
+    fn one_channel_is_picked_out_or_falls_back_to_the_first() {
+        let buf = buffer(&[&[0.1, 0.2], &[0.3, 0.4], &[0.5, 0.6]]);
+        let decoded = AudioBufferRef::F32(std::borrow::Cow::Borrowed(&buf));
+        assert_eq!(to_channel_f32(&decoded, 2), [0.5, 0.6]);
+        assert_eq!(to_channel_f32(&decoded, 3), [0.1, 0.2]);
+    }
+
+    #[test]
+    fn mono_averages_each_frame_across_the_planes() {
+        let buf = buffer(&[&[1.0, 1.0, 0.5], &[-1.0, -1.0, 0.5]]);
+        let decoded = AudioBufferRef::F32(std::borrow::Cow::Borrowed(&buf));
+        let mono = to_mono_f32(&decoded, MonoMix::Average);
+        assert_eq!(mono.len(), 3);
+        assert!(mono[..2].iter().all(|x| x.abs() < 1e-6), "{:?}", mono);
+        assert!((mono[2] - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn itu_mix_weights_each_speaker() {
+        // One frame of 5.1 with only the named speaker sounding.
+        let speaker = |index: usize| {
+            let mut planes = [[0.0f32]; 6];
+            planes[index] = [1.0];
+            let planes: Vec<&[f32]> = planes.iter().map(|plane| &plane[..]).collect();
+            let buf = buffer(&planes);
+            let decoded = AudioBufferRef::F32(std::borrow::Cow::Borrowed(&buf));
+            to_mono_f32(&decoded, MonoMix::Itu)[0]
+        };
+        let half = 0.5;
+        let down_3db = std::f32::consts::FRAC_1_SQRT_2;
+        for (index, weight) in [1.0, 1.0, 2.0 * down_3db, 0.0, down_3db, down_3db]
+            .into_iter()
+            .enumerate()
+        {
+            assert!(
+                (speaker(index) - half * weight).abs() < 1e-6,
+                "speaker {}",
+                index
+            );
+        }
+
+        // Mono stays as it is.
+        let buf = buffer(&[&[0.5]]);
+        let decoded = AudioBufferRef::F32(std::borrow::Cow::Borrowed(&buf));
+        assert_eq!(to_mono_f32(&decoded, MonoMix::Itu), [0.5]);
+    }
+
+    // The f32 values of the lowest and highest samples of one format.
+    fn extremes<S: Sample>(
+        low: S,
+        high: S,
+        wrap: fn(&AudioBuffer<S>) -> AudioBufferRef,
+    ) -> [f32; 2] {
+        let mut buf = AudioBuffer::new(2, SignalSpec::new(44_100, Channels::FRONT_LEFT));
+        buf.render_reserved(Some(2));
+        buf.chan_mut(0).copy_from_slice(&[low, high]);
+        let plane = planes_f32(&wrap(&buf), 0..1).remove(0);
+        [plane[0], plane[1]]
+    }
+
+    #[test]
+    fn full_scale_is_one_in_every_integer_format() {
+        use std::borrow::Cow::Borrowed;
+        use symphonia::core::sample::{i24, u24};
+
+        let all = [
+            extremes(u8::MIN, u8::MAX, |buf| AudioBufferRef::U8(Borrowed(buf))),
+            extremes(u16::MIN, u16::MAX, |buf| AudioBufferRef::U16(Borrowed(buf))),
+            extremes(u24::MIN, u24::MAX, |buf| AudioBufferRef::U24(Borrowed(buf))),
+            extremes(u32::MIN, u32::MAX, |buf| AudioBufferRef::U32(Borrowed(buf))),
+            extremes(i8::MIN, i8::MAX, |buf| AudioBufferRef::S8(Borrowed(buf))),
+            extremes(i16::MIN, i16::MAX, |buf| AudioBufferRef::S16(Borrowed(buf))),
+            extremes(i24::MIN, i24::MAX, |buf| AudioBufferRef::S24(Borrowed(buf))),
+            extremes(i32::MIN, i32::MAX, |buf| AudioBufferRef::S32(Borrowed(buf))),
+        ];
+        for (format, [low, high]) in all.into_iter().enumerate() {
+            // The top is one step short of 1.0, which for 8 bits is 1/128.
+            assert_eq!(low, -1.0, "format {}", format);
+            assert!(
+                (high - 1.0).abs() <= 1.0 / 128.0,
+                "format {}: {}",
+                format,
+                high
+            );
+        }
+    }
+
+    // A WAV file that reads cleanly, or with `fails` stops reading halfway
+    // through, as a disk or network share might.
+    struct WavSource {
+        bytes: io::Cursor<Vec<u8>>,
+        fails: bool,
+    }
+
+    impl Read for WavSource {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.fails && self.bytes.position() * 2 >= self.bytes.get_ref().len() as u64 {
+                return Err(io::Error::other("device went away"));
+            }
+            self.bytes.read(buf)
+        }
+    }
+
+    impl Seek for WavSource {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.bytes.seek(pos)
+        }
+    }
+
+    impl MediaSource for WavSource {
+        fn is_seekable(&self) -> bool {
+            false
+        }
+
+        fn byte_len(&self) -> Option<u64> {
+            None
+        }
+    }
+
+    fn play_source(fails: bool) -> Result<Outcome, DecodeError> {
+        let path = Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/sine.wav"
+        ));
+        let bytes = io::Cursor::new(std::fs::read(path).unwrap());
+        let file = probe(path, Box::new(WavSource { bytes, fails })).unwrap();
+        let options = PlayOptions {
+            audio: false,
+            channel_mode: ChannelMode::Mono,
+            mono_mix: MonoMix::Average,
+            channel: None,
+            controls: false,
+            looping: false,
+            gapless: false,
+            start: None,
+            end: None,
+            stop_at: None,
+            fps: None,
+            window: None,
+            hop: None,
+            realtime: false,
+        };
+        let mut visualizer = Visualizer::new(crate::VisualizerMode::Waveform);
+        play_file(
+            file,
+            &mut visualizer,
+            &options,
+            &mut Carry::default(),
+            &mut io::sink(),
+        )
+    }
+
+    #[test]
+    fn only_the_end_of_the_stream_finishes_a_track() {
+        assert_eq!(play_source(false).unwrap(), Outcome::Finished);
+        let failed = play_source(true);
+        assert!(matches!(
+            failed,
+            Err(DecodeError::Symphonia(SymphoniaError::IoError(_)))
+        ));
+    }
+
+    fn tag(key: StandardTagKey, value: &str) -> Tag {
+        Tag::new(Some(key), "", Value::from(value))
+    }
+
+    #[test]
+    fn header_joins_the_tags_that_are_present() {
+        let tags = [
+            tag(StandardTagKey::Album, "Echoes"),
+            tag(StandardTagKey::TrackTitle, "Intro"),
+            tag(StandardTagKey::TrackNumber, "1"),
+            tag(StandardTagKey::Artist, "Phobos"),
+        ];
+        assert_eq!(
+            track_header(&tags, Path::new("a.flac")),
+            "1. Intro - Phobos - Echoes"
+        );
+    }
+
+    #[test]
+    fn header_falls_back_to_the_file_name() {
+        assert_eq!(track_header(&[], Path::new("music/a.flac")), "a.flac");
+        assert_eq!(track_header(&[], Path::new("-")), "stdin");
+
+        let tags = [tag(StandardTagKey::Artist, "Phobos")];
+        assert_eq!(track_header(&tags, Path::new("a.flac")), "a.flac - Phobos");
+    }
+}
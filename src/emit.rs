@@ -0,0 +1,104 @@
+//! Writing bar levels as text instead of drawing them, for scripts and data
+//! pipelines.
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use clap::ValueEnum;
+
+/// A text format for per-frame bar levels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EmitFormat {
+    /// A `time,bar_0,bar_1,...` header, then one row per frame.
+    Csv,
+    /// One `{"time":...,"levels":[...]}` object per line.
+    Json,
+}
+
+pub(crate) struct LevelWriter {
+    format: EmitFormat,
+    started: bool,
+}
+
+impl LevelWriter {
+    pub(crate) fn new(format: EmitFormat) -> Self {
+        Self {
+            format,
+            started: false,
+        }
+    }
+
+    /// Write one frame's `levels`, each bar as the larger of its two halves, at
+    /// `time` seconds into the track when that's known.
+    pub(crate) fn write<W: Write>(
+        &mut self,
+        out: &mut W,
+        time: Option<Duration>,
+        levels: &[(f32, f32)],
+    ) -> io::Result<()> {
+        let time = time.map(|time| format!("{:.3}", time.as_secs_f64()));
+        let levels = levels
+            .iter()
+            .map(|&(pos, neg)| format!("{:.4}", pos.max(neg)));
+        let mut line = String::new();
+        match self.format {
+            EmitFormat::Csv => {
+                if !self.started {
+                    line.push_str("time");
+                    for bar in 0..levels.len() {
+                        line.push_str(&format!(",bar_{}", bar));
+                    }
+                    line.push('\n');
+                }
+                line.push_str(time.as_deref().unwrap_or_default());
+                for level in levels {
+                    line.push(',');
+                    line.push_str(&level);
+                }
+            }
+            EmitFormat::Json => {
+                line.push_str("{\"time\":");
+                line.push_str(time.as_deref().unwrap_or("null"));
+                line.push_str(",\"levels\":[");
+                line.push_str(&levels.collect::<Vec<_>>().join(","));
+                line.push_str("]}");
+            }
+        }
+        line.push('\n');
+        self.started = true;
+        out.write_all(line.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(format: EmitFormat) -> String {
+        let mut writer = LevelWriter::new(format);
+        let mut out = Vec::new();
+        let levels = [(0.5, 0.25), (0.0, 1.0)];
+        writer
+            .write(&mut out, Some(Duration::from_millis(1500)), &levels)
+            .unwrap();
+        writer.write(&mut out, None, &levels).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_starts_with_a_header() {
+        assert_eq!(
+            lines(EmitFormat::Csv),
+            "time,bar_0,bar_1\n1.500,0.5000,1.0000\n,0.5000,1.0000\n"
+        );
+    }
+
+    #[test]
+    fn json_is_one_object_per_line() {
+        assert_eq!(
+            lines(EmitFormat::Json),
+            "{\"time\":1.500,\"levels\":[0.5000,1.0000]}\n\
+             {\"time\":null,\"levels\":[0.5000,1.0000]}\n"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Frames as grids of styled cells, and the escapes that take the terminal
+//! from one frame to the next.
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::color::{ColorDepth, ansi256_index};
+
+/// How a cell is drawn beyond its glyph. The default is plain text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Style {
+    pub(crate) fg: Option<(u8, u8, u8)>,
+    pub(crate) bold: bool,
+    // Swap foreground and background.
+    pub(crate) inverse: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct Cell {
+    pub(crate) glyph: char,
+    pub(crate) style: Style,
+}
+
+/// A frame under construction, filled left to right and row by row.
+pub(crate) struct Frame {
+    // How foreground colors are written out.
+    depth: ColorDepth,
+    // Every cell, row after row, including those of the row still being filled.
+    cells: Vec<Cell>,
+    // Where each finished row ends in `cells`.
+    row_ends: Vec<usize>,
+}
+
+impl Frame {
+    pub(crate) fn new(depth: ColorDepth) -> Self {
+        Self {
+            depth,
+            cells: Vec::new(),
+            row_ends: Vec::new(),
+        }
+    }
+
+    /// Empty the frame for reuse, keeping its allocations.
+    pub(crate) fn clear(&mut self) {
+        self.cells.clear();
+        self.row_ends.clear();
+    }
+
+    pub(crate) fn push(&mut self, glyph: char) {
+        self.push_styled(Style::default(), glyph);
+    }
+
+    pub(crate) fn push_styled(&mut self, style: Style, glyph: char) {
+        self.cells.push(Cell { glyph, style });
+    }
+
+    pub(crate) fn push_str(&mut self, text: &str) {
+        text.chars().for_each(|glyph| self.push(glyph));
+    }
+
+    pub(crate) fn push_styled_str(&mut self, style: Style, text: &str) {
+        text.chars()
+            .for_each(|glyph| self.push_styled(style, glyph));
+    }
+
+    pub(crate) fn end_row(&mut self) {
+        self.row_ends.push(self.cells.len());
+    }
+
+    /// The finished rows, top to bottom.
+    pub(crate) fn rows(&self) -> impl Iterator<Item = &[Cell]> {
+        let starts = std::iter::once(0).chain(self.row_ends.iter().copied());
+        starts
+            .zip(&self.row_ends)
+            .map(|(start, &end)| &self.cells[start..end])
+    }
+
+    /// Turn every cell of the finished `rows` into a plain space, for rows that
+    /// are drawn some other way.
+    pub(crate) fn blank_rows(&mut self, rows: Range<usize>) {
+        let end = rows.end.min(self.row_ends.len());
+        if rows.start >= end {
+            return;
+        }
+        let first = rows
+            .start
+            .checked_sub(1)
+            .map_or(0, |row| self.row_ends[row]);
+        for cell in &mut self.cells[first..self.row_ends[end - 1]] {
+            *cell = Cell {
+                glyph: ' ',
+                style: Style::default(),
+            };
+        }
+    }
+
+    /// Clear the screen and draw every cell, appending to `out`.
+    pub(crate) fn write_full(&self, out: &mut String) {
+        out.push_str("\x1B[2J\x1B[H");
+        for row in self.rows() {
+            row.iter().for_each(|cell| self.push_cell(out, cell));
+            // Raw mode turns off the tty's newline translation, so return explicitly.
+            out.push_str("\r\n");
+        }
+    }
+
+    /// Redraw only the cells that differ from `prev`, which is assumed to be on
+    /// screen, appending to `out`. Falls back to [`Frame::write_full`] when the
+    /// shape changed.
+    pub(crate) fn write_diff(&self, prev: &Frame, out: &mut String) {
+        let same_shape = self.row_ends.len() == prev.row_ends.len()
+            && self
+                .rows()
+                .zip(prev.rows())
+                .all(|(row, prev_row)| row.len() == prev_row.len());
+        if !same_shape {
+            return self.write_full(out);
+        }
+
+        // Where the cursor is after the last cell written, if anything was.
+        let mut cursor = None;
+        for (y, (row, prev_row)) in self.rows().zip(prev.rows()).enumerate() {
+            for (x, (cell, prev_cell)) in row.iter().zip(prev_row).enumerate() {
+                if cell == prev_cell {
+                    continue;
+                }
+                if cursor != Some((y, x)) {
+                    let _ = write!(out, "\x1B[{};{}H", y + 1, x + 1);
+                }
+                self.push_cell(out, cell);
+                cursor = Some((y, x + 1));
+            }
+        }
+    }
+
+    fn push_cell(&self, out: &mut String, cell: &Cell) {
+        let style = cell.style;
+        if style == Style::default() {
+            out.push(cell.glyph);
+            return;
+        }
+
+        if style.bold {
+            out.push_str("\x1B[1m");
+        }
+        if let Some((r, g, b)) = style.fg {
+            let _ = match self.depth {
+                ColorDepth::Ansi256 => write!(out, "\x1B[38;5;{}m", ansi256_index(r, g, b)),
+                ColorDepth::Truecolor => write!(out, "\x1B[38;2;{};{};{}m", r, g, b),
+            };
+        }
+        if style.inverse {
+            out.push_str("\x1B[7m");
+        }
+        out.push(cell.glyph);
+        out.push_str("\x1B[0m");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(rows: &[&str]) -> Frame {
+        let mut frame = Frame::new(ColorDepth::Truecolor);
+        for row in rows {
+            frame.push_str(row);
+            frame.end_row();
+        }
+        frame
+    }
+
+    fn full(frame: &Frame) -> String {
+        let mut out = String::new();
+        frame.write_full(&mut out);
+        out
+    }
+
+    fn diff(next: &Frame, prev: &Frame) -> String {
+        let mut out = String::new();
+        next.write_diff(prev, &mut out);
+        out
+    }
+
+    #[test]
+    fn full_clears_and_draws_every_row() {
+        assert_eq!(full(&frame(&["ab", "cd"])), "\x1B[2J\x1B[Hab\r\ncd\r\n");
+    }
+
+    #[test]
+    fn unchanged_frame_writes_nothing() {
+        assert_eq!(diff(&frame(&["ab", "cd"]), &frame(&["ab", "cd"])), "");
+    }
+
+    #[test]
+    fn diff_moves_only_to_changed_runs() {
+        let next = frame(&["xbyz", "abcd"]);
+        let prev = frame(&["abcd", "abcd"]);
+        assert_eq!(diff(&next, &prev), "\x1B[1;1Hx\x1B[1;3Hyz");
+    }
+
+    #[test]
+    fn diff_compares_styles_too() {
+        let mut next = Frame::new(ColorDepth::Truecolor);
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+        next.push_styled(bold, 'a');
+        next.end_row();
+        assert_eq!(diff(&next, &frame(&["a"])), "\x1B[1;1H\x1B[1ma\x1B[0m");
+    }
+
+    #[test]
+    fn colors_follow_the_depth() {
+        let red = Style {
+            fg: Some((255, 0, 0)),
+            ..Style::default()
+        };
+        let mut truecolor = Frame::new(ColorDepth::Truecolor);
+        truecolor.push_styled(red, '#');
+        truecolor.end_row();
+        let mut ansi256 = Frame::new(ColorDepth::Ansi256);
+        ansi256.push_styled(red, '#');
+        ansi256.end_row();
+
+        assert!(full(&truecolor).contains("\x1B[38;2;255;0;0m#\x1B[0m"));
+        assert!(full(&ansi256).contains("\x1B[38;5;196m#\x1B[0m"));
+    }
+
+    #[test]
+    fn cleared_frame_starts_over() {
+        let mut frame = frame(&["ab", "cd"]);
+        frame.clear();
+        frame.push_str("e");
+        frame.end_row();
+        assert_eq!(full(&frame), "\x1B[2J\x1B[He\r\n");
+    }
+
+    #[test]
+    fn blanked_rows_keep_their_shape() {
+        let mut blanked = frame(&["ab", "cd", "ef"]);
+        blanked.blank_rows(1..2);
+        assert_eq!(full(&blanked), full(&frame(&["ab", "  ", "ef"])));
+        blanked.blank_rows(2..9);
+        assert_eq!(full(&blanked), full(&frame(&["ab", "  ", "  "])));
+    }
+
+    #[test]
+    fn reshaped_frame_is_drawn_in_full() {
+        let next = frame(&["ab", "cd", "ef"]);
+        assert_eq!(diff(&next, &frame(&["ab", "cd"])), full(&next));
+    }
+}
@@ -0,0 +1,86 @@
+//! Sending RGB images with the Kitty graphics protocol.
+
+use std::fmt::Write;
+
+use image::RgbImage;
+
+use crate::png;
+
+// Escape payloads are sent in pieces of at most this many base64 bytes.
+const CHUNK: usize = 4096;
+
+// Append `image` to `out` as a PNG placed at the cursor and stretched over
+// `cols` x `rows` cells. The image and placement keep the same ids, so each call
+// replaces the last image in place. An image that can't be encoded is left out.
+pub(crate) fn encode(image: &RgbImage, (cols, rows): (usize, usize), out: &mut String) {
+    let Ok(png) = png::encode(image) else {
+        return;
+    };
+    let payload = base64(&png);
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(CHUNK).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            // Transmit and display a PNG, quietly, without moving the cursor.
+            let _ = write!(
+                out,
+                "\x1B_Ga=T,f=100,i=1,p=1,q=2,C=1,c={},r={},m={};",
+                cols, rows, more
+            );
+        } else {
+            let _ = write!(out, "\x1B_Gm={};", more);
+        }
+        // Base64 is plain ASCII.
+        out.extend(chunk.iter().map(|&byte| byte as char));
+        out.push_str("\x1B\\");
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let bits = group.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_pads_partial_groups() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(base64(b""), "");
+    }
+
+    #[test]
+    fn large_images_are_sent_in_chunks() {
+        // Noise compresses badly, so this needs several chunks.
+        let pixels: Vec<u8> = (0..64 * 64 * 3u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect();
+        let image = RgbImage::from_raw(64, 64, pixels).unwrap();
+        let mut out = String::new();
+        encode(&image, (8, 4), &mut out);
+
+        assert!(out.starts_with("\x1B_Ga=T,f=100,i=1,p=1,q=2,C=1,c=8,r=4,m=1;"));
+        assert!(out.contains("\x1B\\\x1B_Gm=1;"));
+        let last = out.rfind("\x1B_G").unwrap();
+        assert!(out[last..].starts_with("\x1B_Gm=0;"));
+        assert!(out.ends_with("\x1B\\"));
+    }
+}
@@ -0,0 +1,100 @@
+//! Real-time audio visualization for the terminal.
+//!
+//! [`decode_file`] plays a file through a [`Visualizer`], writing ANSI frames
+//! to any [`std::io::Write`] sink; [`play_playlist`] plays a list of files,
+//! directories and playlists in turn. The `echoes` binary points them at stdout.
+//!
+//! Everything but the level math in [`analysis`] needs the default `std`
+//! feature. Without it the crate is `no_std`, needing only an allocator.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod analysis;
+#[cfg(feature = "std")]
+mod asciicast;
+#[cfg(feature = "audio")]
+mod audio;
+#[cfg(feature = "std")]
+mod braille;
+#[cfg(feature = "audio")]
+mod capture;
+#[cfg(feature = "std")]
+mod color;
+#[cfg(feature = "std")]
+mod config;
+#[cfg(feature = "std")]
+mod decode;
+#[cfg(feature = "std")]
+mod emit;
+#[cfg(feature = "std")]
+mod frame;
+#[cfg(feature = "std")]
+mod kitty;
+#[cfg(feature = "std")]
+mod loudness;
+#[cfg(feature = "std")]
+mod playlist;
+#[cfg(feature = "std")]
+mod png;
+#[cfg(feature = "std")]
+mod raster;
+#[cfg(feature = "std")]
+mod resample;
+#[cfg(feature = "std")]
+mod sixel;
+#[cfg(all(feature = "std", unix))]
+mod socket;
+#[cfg(feature = "std")]
+mod spectrum;
+#[cfg(feature = "std")]
+mod stats;
+#[cfg(feature = "std")]
+mod terminal;
+#[cfg(feature = "std")]
+mod visualizer;
+
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub use analysis::LevelMode;
+#[cfg(feature = "std")]
+pub use asciicast::CastRecorder;
+#[cfg(feature = "audio")]
+pub use capture::{LiveSource, play_live};
+#[cfg(feature = "std")]
+pub use color::{ColorDepth, Theme};
+#[cfg(feature = "std")]
+pub use config::{ConfigError, VisualizerConfig};
+#[cfg(feature = "std")]
+pub use decode::{ChannelMode, DecodeError, MonoMix, Outcome, PlayOptions, decode_file};
+#[cfg(feature = "std")]
+pub use emit::EmitFormat;
+#[cfg(feature = "std")]
+pub use loudness::Normalization;
+#[cfg(feature = "std")]
+pub use playlist::play_playlist;
+#[cfg(feature = "std")]
+pub use spectrum::{Weighting, WindowFunction};
+#[cfg(feature = "std")]
+pub use terminal::{Screen, ScreenGuard, kitty_supported, sixel_supported, unicode_locale};
+#[cfg(feature = "std")]
+pub use visualizer::{Centerline, EnvelopeMode, Visualizer, VisualizerMode};
+
+// Set from a Ctrl+C handler; the decode loop stops at the next packet.
+#[cfg(feature = "std")]
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Ask any running [`decode_file`] to stop at the next packet. Safe to call
+/// from a signal handler.
+#[cfg(feature = "std")]
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Whether [`interrupt`] has been called.
+#[cfg(feature = "std")]
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
@@ -0,0 +1,216 @@
+//! Short-term loudness after ITU-R BS.1770 / EBU R128: K-weighting, 400 ms
+//! blocks and the absolute and relative gates, over a sliding window.
+
+use std::collections::VecDeque;
+
+use clap::ValueEnum;
+
+// Blocks are 400 ms long and start every 100 ms, so each is four steps.
+const STEP_SECS: f64 = 0.1;
+const STEPS_PER_BLOCK: usize = 4;
+// Three seconds of history, the EBU short-term window.
+const WINDOW_STEPS: usize = 30;
+// Blocks quieter than this are ignored outright, and then any more than
+// RELATIVE_GATE below the loudness of what's left.
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+/// What sets the level that fills the bars.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Normalization {
+    /// Follow the sample peak of recent frames.
+    #[default]
+    Peak,
+    /// Follow perceived loudness (EBU R128 short-term), so masters of very
+    /// different loudness look alike.
+    Loudness,
+}
+
+// A direct form I biquad.
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+// The two K-weighting stages, with coefficients derived for any sample rate
+// the way libebur128 does it.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    // A high shelf modelling the acoustic effect of the head.
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        x: [0.0; 2],
+        y: [0.0; 2],
+    };
+
+    // A high pass that discounts the lowest frequencies.
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        x: [0.0; 2],
+        y: [0.0; 2],
+    };
+
+    [shelf, high_pass]
+}
+
+/// Loudness of the last few seconds of audio, fed a buffer at a time.
+pub(crate) struct LoudnessMeter {
+    sample_rate: u32,
+    filters: Vec<[Biquad; 2]>,
+    step_len: usize,
+    // Weighted energy summed over channels, and frames, in the step being filled.
+    step_energy: f64,
+    step_frames: usize,
+    // Mean square of each finished step, oldest first.
+    steps: VecDeque<f64>,
+}
+
+impl LoudnessMeter {
+    pub(crate) fn new(sample_rate: u32, channels: usize) -> Self {
+        Self {
+            sample_rate,
+            filters: vec![k_weighting(sample_rate); channels],
+            step_len: ((sample_rate as f64 * STEP_SECS) as usize).max(1),
+            step_energy: 0.0,
+            step_frames: 0,
+            steps: VecDeque::new(),
+        }
+    }
+
+    /// Whether this meter was set up for audio of this shape.
+    pub(crate) fn fits(&self, sample_rate: u32, channels: usize) -> bool {
+        self.sample_rate == sample_rate && self.filters.len() == channels
+    }
+
+    /// Measure one buffer, one slice of samples per channel.
+    pub(crate) fn push(&mut self, channels: &[&[f32]]) {
+        let frames = channels
+            .iter()
+            .map(|channel| channel.len())
+            .min()
+            .unwrap_or(0);
+        for i in 0..frames {
+            for (filters, channel) in self.filters.iter_mut().zip(channels) {
+                let weighted = filters
+                    .iter_mut()
+                    .fold(channel[i] as f64, |sample, filter| filter.process(sample));
+                self.step_energy += weighted * weighted;
+            }
+
+            self.step_frames += 1;
+            if self.step_frames == self.step_len {
+                self.steps
+                    .push_back(self.step_energy / self.step_len as f64);
+                if self.steps.len() > WINDOW_STEPS {
+                    self.steps.pop_front();
+                }
+                self.step_energy = 0.0;
+                self.step_frames = 0;
+            }
+        }
+    }
+
+    /// Gated loudness over the window in LUFS, or `None` before the first full
+    /// block or when everything in the window is below the absolute gate.
+    pub(crate) fn loudness(&self) -> Option<f64> {
+        let steps: Vec<f64> = self.steps.iter().copied().collect();
+        let blocks: Vec<f64> = steps
+            .windows(STEPS_PER_BLOCK)
+            .map(|steps| steps.iter().sum::<f64>() / STEPS_PER_BLOCK as f64)
+            .filter(|&power| to_lufs(power) > ABSOLUTE_GATE)
+            .collect();
+        if blocks.is_empty() {
+            return None;
+        }
+
+        let threshold = to_lufs(mean(&blocks)) + RELATIVE_GATE;
+        let gated: Vec<f64> = blocks
+            .into_iter()
+            .filter(|&power| to_lufs(power) > threshold)
+            .collect();
+        Some(to_lufs(mean(&gated)))
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len().max(1) as f64
+}
+
+fn to_lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.max(1e-12).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, amplitude: f32, sample_rate: u32, secs: f32) -> Vec<f32> {
+        let len = (sample_rate as f32 * secs) as usize;
+        (0..len)
+            .map(|n| {
+                amplitude * (std::f32::consts::TAU * freq * n as f32 / sample_rate as f32).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn full_scale_1khz_sine_is_about_minus_3_lufs() {
+        let mut meter = LoudnessMeter::new(48_000, 1);
+        meter.push(&[&sine(1000.0, 1.0, 48_000, 3.0)]);
+        let lufs = meter.loudness().unwrap();
+        assert!((lufs + 3.01).abs() < 0.1, "lufs = {}", lufs);
+    }
+
+    #[test]
+    fn stereo_channels_add_up() {
+        let tone = sine(1000.0, 0.5, 44_100, 2.0);
+        let mut meter = LoudnessMeter::new(44_100, 2);
+        meter.push(&[&tone, &tone]);
+        // About -9 LUFS per channel, and the second channel adds 3 dB.
+        let lufs = meter.loudness().unwrap();
+        assert!((lufs + 6.03).abs() < 0.1, "lufs = {}", lufs);
+    }
+
+    #[test]
+    fn silence_is_gated_out() {
+        let mut meter = LoudnessMeter::new(48_000, 1);
+        meter.push(&[&vec![0.0; 48_000]]);
+        assert_eq!(meter.loudness(), None);
+    }
+
+    #[test]
+    fn nothing_measured_before_a_full_block() {
+        let mut meter = LoudnessMeter::new(48_000, 1);
+        meter.push(&[&sine(1000.0, 1.0, 48_000, 0.3)]);
+        assert_eq!(meter.loudness(), None);
+    }
+}
@@ -1,218 +1,658 @@
-use std::cmp::min;
 use std::fs::File;
-use std::io::{self, Write};
-use std::time::Duration;
-
-use symphonia::core::audio::{AudioBufferRef, Signal};
-use symphonia::core::io::MediaSourceStream;
-use symphonia::default::get_probe;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser};
+use echoes::{
+    CastRecorder, Centerline, ChannelMode, ColorDepth, DecodeError, EmitFormat, EnvelopeMode,
+    LevelMode, MonoMix, Normalization, Outcome, PlayOptions, Screen, ScreenGuard, Theme,
+    Visualizer, VisualizerConfig, VisualizerMode, Weighting, WindowFunction, interrupted,
+    play_playlist,
+};
+use log::{LevelFilter, warn};
+
+/// A real-time audio visualizer for your terminal.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Audio files, directories or .m3u playlists to play, in order; `-` reads
+    /// from stdin.
+    #[arg(required_unless_present_any = ["loopback", "mic"], value_name = "FILE")]
+    files: Vec<PathBuf>,
+
+    /// Visualize whatever the system is playing instead of files, captured
+    /// from the default output device (needs the `audio` feature).
+    #[arg(long, conflicts_with_all = ["files", "start", "end", "looping", "gapless", "window"])]
+    loopback: bool,
+
+    /// Visualize the default input device, a microphone or line in, instead of
+    /// files (needs the `audio` feature).
+    #[arg(
+        long,
+        conflicts_with_all = ["files", "loopback", "start", "end", "looping", "gapless", "window"]
+    )]
+    mic: bool,
+
+    /// Read settings from this TOML file; flags given here override it
+    /// [default: ~/.config/echoes/config.toml, if it exists].
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Number of bars to draw across the screen [default: terminal width].
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    bars: Option<u16>,
+
+    /// What to draw for each buffer.
+    #[arg(long, value_enum, default_value_t)]
+    mode: VisualizerMode,
+
+    /// Window applied to each FFT frame in spectrum mode.
+    #[arg(long, value_enum, default_value_t)]
+    window_fn: WindowFunction,
+
+    /// Frequency weighting for spectrum magnitudes: A-weighting, or a pink
+    /// tilt of +3 dB per octave.
+    #[arg(long, value_enum, default_value_t)]
+    weighting: Weighting,
+
+    /// Analyze the spectrum at the file's own rate instead of at 44.1 kHz, so
+    /// high-rate files spread their bars over a wider frequency range.
+    #[arg(long)]
+    no_resample: bool,
+
+    /// Color ramp for the bars.
+    #[arg(long, value_enum, default_value_t)]
+    theme: Theme,
+
+    /// Color palette to emit [default: truecolor if $COLORTERM advertises it].
+    #[arg(long, value_enum)]
+    colors: Option<ColorDepth>,
+
+    /// Dim every color by this factor, 0.0 to 1.0.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_fraction)]
+    brightness: f32,
+
+    /// Scale bars to the sample peak or to perceived loudness (EBU R128).
+    #[arg(long, value_enum, default_value_t)]
+    normalize: Normalization,
+
+    /// Multiply levels by this fixed factor instead of following the peak, so
+    /// tracks can be compared; loud passages clip at the top.
+    #[arg(long, value_name = "FACTOR", value_parser = parse_positive)]
+    gain: Option<f32>,
+
+    /// How waveform bars measure their slice of samples.
+    #[arg(long, value_enum, default_value_t)]
+    level_mode: LevelMode,
+
+    /// Draw anything quieter than this many dBFS as silence.
+    #[arg(
+        long,
+        value_name = "DBFS",
+        default_value_t = -60.0,
+        allow_negative_numbers = true,
+        value_parser = parse_dbfs
+    )]
+    gate: f32,
+
+    /// Scale bar heights in decibels rather than linearly.
+    #[arg(long)]
+    db: bool,
+
+    /// Quietest level shown with --db, in dB below the peak.
+    #[arg(
+        long,
+        value_name = "DB",
+        default_value_t = -60.0,
+        allow_negative_numbers = true,
+        value_parser = parse_db_floor
+    )]
+    db_floor: f32,
+
+    /// Raise bar heights to this power: below 1.0 shows quiet detail, above 1.0
+    /// emphasizes peaks.
+    #[arg(long, value_name = "G", default_value_t = 1.0, value_parser = parse_positive)]
+    gamma: f32,
+
+    /// How slowly the auto-gain follows quieter audio, 0.0 to 1.0: the share of
+    /// the peak kept over each thirtieth of a second.
+    #[arg(long, default_value_t = 0.92, value_parser = parse_fraction)]
+    decay: f32,
+
+    /// Weight of each new frame in the bar levels, 0.0 to 1.0; 1.0 turns
+    /// smoothing off.
+    #[arg(long, default_value_t = 0.65, value_parser = parse_fraction)]
+    smoothing: f32,
+
+    /// Smooth bar levels by this time constant in milliseconds instead, so
+    /// they ease at the same pace whatever the frame rate.
+    #[arg(long, value_name = "MS", conflicts_with = "smoothing", value_parser = parse_millis)]
+    smoothing_ms: Option<f64>,
+
+    /// How bar levels ease between frames; `attack-release` rises and falls
+    /// at separate speeds in place of the smoothing.
+    #[arg(long, value_enum, value_name = "MODE", default_value_t)]
+    envelope: EnvelopeMode,
+
+    /// Rise time for attack/release easing, in milliseconds; giving it turns
+    /// that mode on.
+    #[arg(long, value_name = "MS", default_value_t = 10.0, value_parser = parse_millis)]
+    attack: f64,
+
+    /// Fall time for attack/release easing, in milliseconds; giving it turns
+    /// that mode on.
+    #[arg(long, value_name = "MS", default_value_t = 300.0, value_parser = parse_millis)]
+    release: f64,
+
+    /// Flash the display on beats.
+    #[arg(long)]
+    beat_flash: bool,
+
+    /// Show each channel's peak level in dBFS under the visualization.
+    #[arg(long)]
+    readout: bool,
+
+    /// Show the frame rate and the time per frame spent decoding, analyzing
+    /// and drawing under the visualization, to see which is holding it back.
+    #[arg(long)]
+    stats: bool,
+
+    /// Label round frequencies under the bars in spectrum mode.
+    #[arg(long)]
+    freq_labels: bool,
+
+    /// Don't mark each bar's recent peak.
+    #[arg(long)]
+    no_peak_hold: bool,
+
+    /// Round bars to whole cells instead of using eighth-block tips.
+    #[arg(long)]
+    solid_blocks: bool,
+
+    /// Anti-alias bar edges with half blocks, stepping down between neighbours.
+    #[arg(long)]
+    smooth_edges: bool,
+
+    /// Draw the centerline with this character, or `none` to hide it and give
+    /// the bars the full height.
+    #[arg(long, value_name = "CHAR|none")]
+    centerline: Option<Centerline>,
+
+    /// Draw the bar modes as single bars up from the bottom, over the full
+    /// height, instead of out from the centerline.
+    #[arg(long)]
+    upward: bool,
+
+    /// Turn the bar and scope modes upside down; with --upward, bars hang
+    /// from the top.
+    #[arg(long)]
+    flip: bool,
+
+    /// Draw the bar and scope modes with Braille dots for finer detail.
+    #[arg(long)]
+    braille: bool,
+
+    /// Draw with ASCII `#` and `-` only [default: on unless the locale is UTF-8].
+    #[arg(long)]
+    ascii: bool,
+
+    /// Downmix to mono, or show left and right side by side.
+    #[arg(long, value_enum, default_value_t)]
+    channel_mode: ChannelMode,
+
+    /// How channels are combined in the mono downmix; `itu` weights surround
+    /// speakers the standard way.
+    #[arg(long, value_enum, value_name = "MIX", default_value_t)]
+    mono_mix: MonoMix,
+
+    /// Draw only this channel of the file, counting from 0 (e.g. 2 for the
+    /// center of a 5.1 track), instead of a mono or stereo mix.
+    #[arg(long, value_name = "INDEX", conflicts_with = "channel_mode")]
+    channel: Option<usize>,
+
+    /// Draw at most this many frames per second; audio in between is folded
+    /// into the next frame [default: one frame per decoded buffer].
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    fps: Option<u32>,
+
+    /// Analyze fixed windows of this many samples (e.g. 2048), whatever size
+    /// the codec decodes in [default: each decoded buffer as it comes].
+    #[arg(long, value_name = "SAMPLES", value_parser = clap::value_parser!(u64).range(1..))]
+    window: Option<u64>,
+
+    /// Start each --window this many samples after the last, overlapping them
+    /// when it's less than the window [default: the window].
+    #[arg(long, value_name = "SAMPLES", requires = "window", value_parser = clap::value_parser!(u64).range(1..))]
+    hop: Option<u64>,
+
+    /// Skip this many seconds into each file before drawing.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    start: Option<Duration>,
+
+    /// Stop each file this many seconds in, counted from its beginning.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    end: Option<Duration>,
+
+    /// Exit after this many seconds, whatever is playing, as for a demo or a
+    /// screenshot.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    duration: Option<Duration>,
+
+    /// Repeat each file until interrupted.
+    #[arg(long = "loop")]
+    looping: bool,
+
+    /// Run each track straight into the next, keeping the sound and the bars
+    /// going across the join, as for live albums and DJ mixes.
+    #[arg(long)]
+    gapless: bool,
+
+    /// Draw the visualization as a Sixel image on terminals that support it,
+    /// falling back to characters elsewhere.
+    #[arg(long, conflicts_with = "kitty")]
+    sixel: bool,
+
+    /// Draw the visualization with the Kitty graphics protocol on terminals
+    /// that support it, falling back to characters elsewhere.
+    #[arg(long)]
+    kitty: bool,
+
+    /// Render frames as PNG images into this directory, 30 per second of audio,
+    /// as fast as possible and without sound.
+    #[arg(long, value_name = "DIR")]
+    png_out: Option<PathBuf>,
+
+    /// Print each frame's timestamp and bar levels (0.0 to 1.0) on stdout, or
+    /// the --output stream, as CSV rows or JSON lines instead of drawing, as
+    /// fast as the file decodes.
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["png_out", "sixel", "kitty"])]
+    emit: Option<EmitFormat>,
+
+    /// Also save the session to this file as an asciinema recording, with
+    /// every frame timed as it was shown.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["png_out", "emit"])]
+    record: Option<PathBuf>,
+
+    /// Also send each frame's levels as JSON lines to this Unix domain socket
+    /// or named pipe, for LED controllers and the like. Frames are dropped
+    /// while nothing is reading.
+    #[arg(long, value_name = "PATH")]
+    emit_socket: Option<PathBuf>,
+
+    /// Draw frames, or emit levels, on this stream, leaving the other for
+    /// whatever else the pipeline needs.
+    #[arg(long, value_enum, value_name = "STREAM", default_value_t)]
+    output: Screen,
+
+    /// Don't play sound, only draw (playback needs the `audio` feature).
+    #[arg(long)]
+    no_audio: bool,
+
+    /// Print nothing on stderr but fatal errors.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Say more on stderr: `-v` for progress, `-vv` for format and codec
+    /// details, `-vvv` for everything.
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+}
 
-fn main() {
-    decode_file("audio/test.mp3");
+// `$XDG_CONFIG_HOME/echoes/config.toml`, falling back to `~/.config`.
+fn default_config_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("echoes").join("config.toml"))
 }
 
-// Decode an audio file and render frames into the terminal.
-fn decode_file(filename: &str) {
-    let src = Box::new(File::open(filename).expect("failed to open audio file"));
-    let mss = MediaSourceStream::new(src, Default::default());
-
-    let probe = get_probe()
-        .format(
-            &Default::default(),
-            mss,
-            &Default::default(),
-            &Default::default(),
-        )
-        .expect("unsupported media format");
-
-    let mut format = probe.format;
-    let track = format.default_track().expect("no default track in file");
-
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &Default::default())
-        .expect("failed to build decoder");
-
-    let mut visualizer = Visualizer::new();
-
-    while let Ok(packet) = format.next_packet() {
-        let decoded = decoder
-            .decode(&packet)
-            .expect("decoder error while reading packet");
-
-        match decoded {
-            AudioBufferRef::F32(buf) => {
-                visualizer.render(buf.chan(0));
-            }
-            AudioBufferRef::S16(buf) => {
-                let samples: Vec<f32> = buf.chan(0).iter().map(|x| *x as f32 / 32_768.0).collect();
-                visualizer.render(&samples);
-            }
-            _ => eprintln!("Unsupported sample format"),
-        }
+// Settings from the config file, on top of `base`. A missing file is only an
+// error when it was asked for by name.
+fn load_config(path: Option<&Path>, base: VisualizerConfig) -> Result<VisualizerConfig, String> {
+    let (path, required) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_config_path() {
+            Some(path) => (path, false),
+            None => return Ok(base),
+        },
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound && !required => return Ok(base),
+        Err(err) => return Err(format!("{}: {}", path.display(), err)),
+    };
+    base.apply_toml(&text)
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
 
-        std::thread::sleep(Duration::from_millis(33));
+fn parse_db_floor(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(floor) if floor <= -1.0 && floor.is_finite() => Ok(floor),
+        Ok(_) => Err("must be -1 or lower".to_string()),
+        Err(err) => Err(err.to_string()),
     }
 }
 
-struct Visualizer {
-    peak: f32,
-    prev_columns: Vec<(f32, f32)>,
+fn parse_dbfs(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(dbfs) if dbfs <= 0.0 => Ok(dbfs),
+        Ok(_) => Err("must be 0 or lower".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
 }
 
-impl Visualizer {
-    fn new() -> Self {
-        Self {
-            peak: 0.25,
-            prev_columns: Vec::new(),
-        }
+fn parse_positive(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(number) if number > 0.0 && number.is_finite() => Ok(number),
+        Ok(_) => Err("must be greater than 0".to_string()),
+        Err(err) => Err(err.to_string()),
     }
+}
 
-    fn render(&mut self, samples: &[f32]) {
-        const NUM_BARS: usize = 64;
-        const MAX_HEIGHT: usize = 21;
-
-        if samples.is_empty() {
-            return;
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    match value.parse::<f64>() {
+        Ok(seconds) if seconds >= 0.0 && seconds.is_finite() => {
+            Ok(Duration::from_secs_f64(seconds))
         }
+        Ok(_) => Err("must be 0 or more".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
 
-        print!("\x1B[2J\x1B[H");
-
-        let chunk_size = (samples.len() + NUM_BARS - 1) / NUM_BARS;
-        let mut columns: Vec<(f32, f32)> = Vec::with_capacity(NUM_BARS);
-        let mut frame_peak = 0.0f32;
-
-        for i in 0..NUM_BARS {
-            let start = i * chunk_size;
-            if start >= samples.len() {
-                columns.push((0.0, 0.0));
-                continue;
-            }
-
-            let end = min(start + chunk_size, samples.len());
-            let chunk = &samples[start..end];
-
-            if chunk.is_empty() {
-                columns.push((0.0, 0.0));
-                continue;
-            }
+fn parse_millis(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(ms) if ms >= 0.0 && ms.is_finite() => Ok(ms),
+        Ok(_) => Err("must be 0 or more".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
 
-            let mut pos_peak = 0.0f32;
-            let mut pos_sum = 0.0f32;
-            let mut pos_count = 0u32;
-            let mut neg_peak = 0.0f32;
-            let mut neg_sum = 0.0f32;
-            let mut neg_count = 0u32;
-
-            for &sample in chunk {
-                if sample > 0.0 {
-                    pos_peak = pos_peak.max(sample);
-                    pos_sum += sample;
-                    pos_count += 1;
-                } else if sample < 0.0 {
-                    let magnitude = -sample;
-                    neg_peak = neg_peak.max(magnitude);
-                    neg_sum += magnitude;
-                    neg_count += 1;
-                }
-            }
+fn parse_fraction(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(fraction),
+        Ok(_) => Err("must be between 0.0 and 1.0".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
 
-            let pos_level = if pos_count > 0 {
-                let avg = pos_sum / pos_count as f32;
-                0.75 * pos_peak + 0.25 * avg
+// Play the files on the command line into `out`, or the live capture asked for.
+fn play<W: Write>(
+    args: &Args,
+    visualizer: &mut Visualizer,
+    options: &PlayOptions,
+    out: &mut W,
+) -> Result<Outcome, DecodeError> {
+    if args.loopback || args.mic {
+        #[cfg(feature = "audio")]
+        {
+            let source = if args.mic {
+                echoes::LiveSource::Microphone
             } else {
-                0.0
+                echoes::LiveSource::Loopback
             };
+            return echoes::play_live(source, visualizer, options, out);
+        }
+        #[cfg(not(feature = "audio"))]
+        return Err(DecodeError::Capture(
+            "live capture needs a build with the `audio` feature".to_string(),
+        ));
+    }
+    play_playlist(&args.files, visualizer, options, out)
+}
 
-            let neg_level = if neg_count > 0 {
-                let avg = neg_sum / neg_count as f32;
-                0.75 * neg_peak + 0.25 * avg
-            } else {
-                0.0
-            };
+fn main() -> ExitCode {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    // Whether a flag was typed, rather than left at its default, so the config
+    // file only gives way to flags that were.
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if let (Some(start), Some(end)) = (args.start, args.end)
+        && start >= end
+    {
+        Args::command()
+            .error(ErrorKind::ValueValidation, "--start must be before --end")
+            .exit();
+    }
+    if let (Some(window), Some(hop)) = (args.window, args.hop)
+        && hop > window
+    {
+        Args::command()
+            .error(
+                ErrorKind::ValueValidation,
+                "--hop can't be longer than --window",
+            )
+            .exit();
+    }
+    args.output.select();
+
+    // Logs go to stderr, filtered by `$RUST_LOG`. The flags override it for
+    // echoes' own records, and without either only echoes' warnings show.
+    let mut logger = env_logger::Builder::new();
+    logger.parse_default_env();
+    let level = match (args.quiet, args.verbose) {
+        (true, _) => Some(LevelFilter::Error),
+        (false, 0) if std::env::var_os("RUST_LOG").is_some() => None,
+        (false, 0) => Some(LevelFilter::Warn),
+        (false, 1) => Some(LevelFilter::Info),
+        (false, 2) => Some(LevelFilter::Debug),
+        (false, _) => Some(LevelFilter::Trace),
+    };
+    if let Some(level) = level {
+        logger.filter_module("echoes", level);
+    }
+    logger.init();
 
-            frame_peak = frame_peak.max(pos_level.max(neg_level));
-            columns.push((pos_level, neg_level));
-        }
+    if let Err(err) = ctrlc::set_handler(echoes::interrupt) {
+        warn!("can't catch Ctrl+C: {}", err);
+    }
 
-        if frame_peak > self.peak {
-            self.peak = frame_peak;
+    let base = VisualizerConfig::default().color_depth(ColorDepth::detect());
+    let mut config = match load_config(args.config.as_deref(), base) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("echoes: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    if given("mode") {
+        config = config.mode(args.mode);
+    }
+    if let Some(bars) = args.bars {
+        config = config.bars(bars as usize);
+    }
+    if given("window_fn") {
+        config = config.window(args.window_fn);
+    }
+    if given("weighting") {
+        config = config.weighting(args.weighting);
+    }
+    if args.no_resample {
+        config = config.resample(false);
+    }
+    if given("theme") {
+        config = config.theme(args.theme);
+    }
+    if let Some(colors) = args.colors {
+        config = config.color_depth(colors);
+    }
+    if given("brightness") {
+        config = config.brightness(args.brightness);
+    }
+    if given("normalize") {
+        config = config.normalization(args.normalize);
+    }
+    if let Some(gain) = args.gain {
+        config = config.gain(gain);
+    }
+    if given("level_mode") {
+        config = config.level_mode(args.level_mode);
+    }
+    if given("gate") {
+        config = config.gate(args.gate);
+    }
+    if args.db {
+        config = config.db_floor(Some(args.db_floor));
+    }
+    if given("gamma") {
+        config = config.gamma(args.gamma);
+    }
+    if given("decay") {
+        config = config.decay(args.decay);
+    }
+    if given("smoothing") {
+        config = config.smoothing(args.smoothing);
+    }
+    if let Some(ms) = args.smoothing_ms {
+        config = config.smoothing_time(Duration::from_secs_f64(ms / 1000.0));
+    }
+    if given("envelope") {
+        config = config.envelope(args.envelope);
+    }
+    if given("attack") || given("release") {
+        if !given("envelope") {
+            config = config.envelope(EnvelopeMode::AttackRelease);
+        }
+        if given("attack") {
+            config = config.attack(Duration::from_secs_f64(args.attack / 1000.0));
+        }
+        if given("release") {
+            config = config.release(Duration::from_secs_f64(args.release / 1000.0));
+        }
+    }
+    if args.beat_flash {
+        config = config.beat_flash(true);
+    }
+    if args.readout {
+        config = config.readout(true);
+    }
+    if args.freq_labels {
+        config = config.freq_labels(true);
+    }
+    if args.no_peak_hold {
+        config = config.peak_hold(false);
+    }
+    if args.solid_blocks {
+        config = config.partial_blocks(false);
+    }
+    if args.smooth_edges {
+        config = config.smooth_edges(true);
+    }
+    if let Some(centerline) = args.centerline {
+        config = config.centerline(centerline);
+    }
+    if args.upward {
+        config = config.upward(true);
+    }
+    if args.flip {
+        config = config.flip(true);
+    }
+    if args.braille {
+        config = config.braille(true);
+    }
+    if args.ascii || !echoes::unicode_locale() {
+        config = config.ascii(true);
+    }
+    let mut visualizer = Visualizer::from_config(config);
+    if args.sixel {
+        if echoes::sixel_supported() {
+            visualizer = visualizer.with_sixel(true);
         } else {
-            const DECAY: f32 = 0.92;
-            self.peak = self.peak * DECAY + frame_peak * (1.0 - DECAY);
+            warn!("this terminal doesn't advertise Sixel support; drawing with characters");
         }
-
-        let peak = self.peak.max(1e-3);
-
-        if self.prev_columns.len() != NUM_BARS {
-            self.prev_columns = vec![(0.0, 0.0); NUM_BARS];
+    }
+    if args.kitty {
+        if echoes::kitty_supported() {
+            visualizer = visualizer.with_kitty(true);
+        } else {
+            warn!(
+                "this terminal doesn't look like it speaks the Kitty graphics protocol; drawing with characters"
+            );
         }
-
-        let smoothed: Vec<(f32, f32)> = columns
-            .iter()
-            .zip(self.prev_columns.iter())
-            .map(|(&(pos, neg), &(prev_pos, prev_neg))| {
-                let norm_pos = (pos / peak).clamp(0.0, 1.0);
-                let norm_neg = (neg / peak).clamp(0.0, 1.0);
-                let blend = 0.65;
-                let new_pos = blend * norm_pos + (1.0 - blend) * prev_pos;
-                let new_neg = blend * norm_neg + (1.0 - blend) * prev_neg;
-                (new_pos, new_neg)
-            })
-            .collect();
-
-        self.prev_columns.copy_from_slice(&smoothed);
-
-        const TOTAL_ROWS: usize = MAX_HEIGHT;
-        let mid_row = TOTAL_ROWS / 2;
-        let top_rows = mid_row;
-        let mut frame = String::with_capacity((TOTAL_ROWS + 1) * (NUM_BARS * 8));
-
-        for row in 0..TOTAL_ROWS {
-            for &(pos, neg) in &smoothed {
-                let pos_rows = (pos * top_rows as f32).round() as usize;
-                let neg_rows = (neg * top_rows as f32).round() as usize;
-
-                if row < mid_row {
-                    let threshold = top_rows.saturating_sub(pos_rows);
-                    if row >= threshold {
-                        frame.push_str(color_for(pos));
-                        frame.push('█');
-                        frame.push_str("\x1B[0m");
-                    } else {
-                        frame.push(' ');
-                    }
-                } else if row == mid_row {
-                    frame.push('─');
-                } else {
-                    let offset = row - mid_row - 1;
-                    if offset < neg_rows {
-                        frame.push_str(color_for(neg));
-                        frame.push('█');
-                        frame.push_str("\x1B[0m");
-                    } else {
-                        frame.push(' ');
-                    }
+    }
+    if let Some(dir) = &args.png_out {
+        if let Err(err) = std::fs::create_dir_all(dir) {
+            eprintln!("echoes: {}: {}", dir.display(), err);
+            return ExitCode::FAILURE;
+        }
+        visualizer = visualizer.with_png_output(dir.clone());
+    }
+    if let Some(format) = args.emit {
+        visualizer = visualizer.with_emit(format);
+    }
+    if args.stats {
+        visualizer = visualizer.with_stats(true);
+    }
+    if let Some(path) = &args.emit_socket {
+        #[cfg(unix)]
+        {
+            visualizer = visualizer.with_level_socket(path.clone());
+        }
+        #[cfg(not(unix))]
+        {
+            eprintln!(
+                "echoes: {}: sockets and named pipes need a Unix system",
+                path.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    }
+    let realtime = args.png_out.is_none() && args.emit.is_none();
+    let mut recorder = match &args.record {
+        Some(path) => {
+            let recorder = File::create(path)
+                .and_then(|file| CastRecorder::new(args.output.writer(), BufWriter::new(file)));
+            match recorder {
+                Ok(recorder) => Some(recorder),
+                Err(err) => {
+                    eprintln!("echoes: {}: {}", path.display(), err);
+                    return ExitCode::FAILURE;
                 }
             }
-            frame.push('\n');
         }
-
-        print!("{}", frame);
-        let _ = io::stdout().flush();
-    }
-}
-
-fn color_for(level: f32) -> &'static str {
-    let scaled = level.powf(0.6);
-    if scaled < 0.2 {
-        "\x1B[38;5;39m" // teal
-    } else if scaled < 0.4 {
-        "\x1B[38;5;48m" // green
-    } else if scaled < 0.6 {
-        "\x1B[38;5;190m" // yellow
-    } else if scaled < 0.8 {
-        "\x1B[38;5;208m" // orange
-    } else {
-        "\x1B[38;5;196m" // red
+        None => None,
+    };
+
+    let options = PlayOptions {
+        audio: !args.no_audio && realtime,
+        channel_mode: args.channel_mode,
+        mono_mix: args.mono_mix,
+        channel: args.channel,
+        controls: realtime && io::stdin().is_terminal() && args.output.is_terminal(),
+        looping: args.looping,
+        gapless: args.gapless,
+        start: args.start,
+        end: args.end,
+        stop_at: args.duration.map(|duration| Instant::now() + duration),
+        fps: args.fps,
+        window: args.window.map(|window| window as usize),
+        hop: args.hop.map(|hop| hop as usize),
+        realtime,
+    };
+
+    // The screen guard has to be gone before reporting, or the message lands on
+    // the alternate screen and vanishes with it.
+    let result = {
+        let _screen = realtime.then(|| ScreenGuard::enter(options.controls));
+        match recorder.as_mut() {
+            Some(recorder) => play(&args, &mut visualizer, &options, recorder),
+            None => play(&args, &mut visualizer, &options, &mut args.output.writer()),
+        }
+    };
+
+    match result {
+        // The conventional status for a process stopped by SIGINT.
+        Ok(_) if interrupted() => ExitCode::from(130),
+        Ok(_) => ExitCode::SUCCESS,
+        // Whoever was reading the frames (`| head`, say) has gone away.
+        Err(DecodeError::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("echoes: {}", err);
+            ExitCode::FAILURE
+        }
     }
 }
@@ -0,0 +1,154 @@
+//! Expanding directories and `.m3u` playlists into tracks, and playing them in turn.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use log::{info, warn};
+
+use crate::decode::{Carry, DecodeError, OpenFile, Outcome, PlayOptions, open_file, play_file};
+use crate::interrupted;
+use crate::visualizer::Visualizer;
+
+// Extensions picked up when listing a directory. Some need a format feature to
+// decode; without it they're skipped with a warning like any other bad file.
+const AUDIO_EXTENSIONS: [&str; 12] = [
+    "aif", "aiff", "caf", "flac", "m4a", "mka", "mp3", "mp4", "oga", "ogg", "wav", "webm",
+];
+
+/// Play every track named by `paths` through one `visualizer`. Directories
+/// contribute their audio files in sorted order and `.m3u` playlists their
+/// entries. Tracks that can't be opened or decoded are skipped with a warning;
+/// failing to write to `out` stops playback. With `options.gapless`, each
+/// track is opened in the background while the one before it plays.
+pub fn play_playlist<W: Write>(
+    paths: &[PathBuf],
+    visualizer: &mut Visualizer,
+    options: &PlayOptions,
+    out: &mut W,
+) -> Result<Outcome, DecodeError> {
+    let tracks = expand(paths);
+    let mut carry = Carry::default();
+    let mut upcoming: Option<JoinHandle<Result<OpenFile, DecodeError>>> = None;
+    let mut played = false;
+    for (i, path) in tracks.iter().enumerate() {
+        let file = match upcoming.take() {
+            Some(opening) => opening
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic)),
+            None => open_file(path),
+        };
+        if options.gapless
+            && let Some(next) = tracks.get(i + 1).cloned()
+        {
+            upcoming = Some(thread::spawn(move || open_file(&next)));
+        }
+
+        info!("playing {}", path.display());
+        let result = file.and_then(|file| play_file(file, visualizer, options, &mut carry, out));
+        match result {
+            Ok(Outcome::Finished) if !interrupted() => played = true,
+            Ok(outcome) => return Ok(outcome),
+            Err(DecodeError::Io(err)) => return Err(DecodeError::Io(err)),
+            Err(err) => warn!("skipping {}: {}", path.display(), err),
+        }
+    }
+
+    if played {
+        Ok(Outcome::Finished)
+    } else {
+        Err(DecodeError::NothingToPlay)
+    }
+}
+
+// The tracks behind `paths`, in play order.
+fn expand(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut tracks = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            match list_dir(path) {
+                Ok(files) => tracks.extend(files),
+                Err(err) => warn!("skipping {}: {}", path.display(), err),
+            }
+        } else if is_m3u(path) {
+            match fs::read_to_string(path) {
+                Ok(text) => {
+                    let base = path.parent().unwrap_or(Path::new(""));
+                    tracks.extend(expand(&parse_m3u(&text, base)));
+                }
+                Err(err) => warn!("skipping {}: {}", path.display(), err),
+            }
+        } else {
+            tracks.push(path.clone());
+        }
+    }
+    tracks
+}
+
+// Audio files directly inside `dir`, sorted by name.
+fn list_dir(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && has_extension(path, &AUDIO_EXTENSIONS))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn is_m3u(path: &Path) -> bool {
+    has_extension(path, &["m3u", "m3u8"])
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            extensions
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+}
+
+// Entries of an M3U playlist, with relative ones resolved against `base`.
+// Blank lines and `#` comments (including `#EXTINF` info) are skipped.
+fn parse_m3u(text: &str, base: &Path) -> Vec<PathBuf> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn m3u_skips_comments_and_blank_lines() {
+        let text = "#EXTM3U\n#EXTINF:123,Artist - Title\nintro.mp3\n\n  song.flac  \r\n";
+        assert_eq!(
+            parse_m3u(text, Path::new("music")),
+            vec![
+                PathBuf::from("music/intro.mp3"),
+                PathBuf::from("music/song.flac")
+            ]
+        );
+    }
+
+    #[test]
+    fn m3u_keeps_absolute_entries() {
+        assert_eq!(
+            parse_m3u("/tmp/a.wav\n", Path::new("music")),
+            vec![PathBuf::from("/tmp/a.wav")]
+        );
+    }
+
+    #[test]
+    fn extensions_match_case_insensitively() {
+        assert!(has_extension(Path::new("a.FLAC"), &AUDIO_EXTENSIONS));
+        assert!(is_m3u(Path::new("list.M3U8")));
+        assert!(!has_extension(Path::new("cover.jpg"), &AUDIO_EXTENSIONS));
+        assert!(!has_extension(Path::new("README"), &AUDIO_EXTENSIONS));
+    }
+}
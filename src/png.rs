@@ -0,0 +1,57 @@
+//! Rasterizing frames into numbered PNG images, for turning playback into video.
+
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use image::{ImageFormat, ImageResult, RgbImage};
+
+use crate::frame::Frame;
+use crate::raster::rasterize;
+
+// Pixels per character cell.
+const CELL: (usize, usize) = (8, 16);
+// Images come out at a fixed rate so they can be muxed with the audio.
+const FPS: f64 = 30.0;
+
+/// Writes `frame_000000.png`, `frame_000001.png`, ... into a directory at 30
+/// images per second of playback.
+pub(crate) struct PngOutput {
+    dir: PathBuf,
+    written: u64,
+}
+
+impl PngOutput {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir, written: 0 }
+    }
+
+    /// Write `frame` as however many images it takes to catch up with `elapsed`
+    /// playback time, or as exactly one image when the time isn't known.
+    pub(crate) fn write(&mut self, frame: &Frame, elapsed: Option<Duration>) -> io::Result<()> {
+        let due = match elapsed {
+            Some(elapsed) => (elapsed.as_secs_f64() * FPS).ceil() as u64,
+            None => self.written + 1,
+        };
+        if self.written >= due {
+            return Ok(());
+        }
+
+        let image = rasterize(frame, 0..frame.rows().count(), CELL);
+        let png = encode(&image).map_err(io::Error::other)?;
+        while self.written < due {
+            let path = self.dir.join(format!("frame_{:06}.png", self.written));
+            fs::write(path, &png)?;
+            self.written += 1;
+        }
+        Ok(())
+    }
+}
+
+// `image` as a complete PNG file.
+pub(crate) fn encode(image: &RgbImage) -> ImageResult<Vec<u8>> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
@@ -0,0 +1,119 @@
+//! Drawing frames as RGB pixels, for the image outputs.
+
+use std::ops::Range;
+
+use image::{Rgb, RgbImage};
+
+use crate::braille::DOT_BITS;
+use crate::frame::{Cell, Frame};
+
+// Used for cells drawn without a color.
+const DEFAULT_FG: (u8, u8, u8) = (0xd0, 0xd0, 0xd0);
+const BACKGROUND: (u8, u8, u8) = (0, 0, 0);
+
+// An image of `rows` of `frame` at `cell` (width, height) pixels per cell,
+// with block, line, shade and Braille glyphs drawn as shapes. Text, like the
+// header, has no shapes and is left out.
+pub(crate) fn rasterize(frame: &Frame, rows: Range<usize>, cell: (usize, usize)) -> RgbImage {
+    let (cell_width, cell_height) = cell;
+    let lines = || frame.rows().skip(rows.start).take(rows.len());
+    let width = lines().map(<[Cell]>::len).max().unwrap_or(0) * cell_width;
+    let height = lines().count() * cell_height;
+    let mut image = RgbImage::new(width as u32, height as u32);
+
+    for (row, cells) in lines().enumerate() {
+        for (col, cell) in cells.iter().enumerate() {
+            let fg = cell.style.fg.unwrap_or(DEFAULT_FG);
+            for y in 0..cell_height {
+                for x in 0..cell_width {
+                    let mut amount = coverage(cell, x, y, cell_width, cell_height);
+                    if cell.style.inverse {
+                        amount = 1.0 - amount;
+                    }
+                    let (px, py) = (col * cell_width + x, row * cell_height + y);
+                    let rgb = Rgb([
+                        mix(BACKGROUND.0, fg.0, amount),
+                        mix(BACKGROUND.1, fg.1, amount),
+                        mix(BACKGROUND.2, fg.2, amount),
+                    ]);
+                    image.put_pixel(px as u32, py as u32, rgb);
+                }
+            }
+        }
+    }
+
+    image
+}
+
+// How much of the pixel at `(x, y)` within a `width` x `height` cell the glyph
+// covers.
+fn coverage(cell: &Cell, x: usize, y: usize, width: usize, height: usize) -> f32 {
+    let mid = height / 2;
+    let covered = match cell.glyph {
+        '█' | '#' => true,
+        // Lower eighth blocks, ▁ through ▇.
+        glyph @ '\u{2581}'..='\u{2587}' => {
+            let eighths = glyph as usize - 0x2580;
+            y >= height - eighths * height / 8
+        }
+        // Left eighth blocks, ▉ (seven eighths) through ▏.
+        glyph @ '\u{2589}'..='\u{258F}' => {
+            let eighths = 0x2590 - glyph as usize;
+            x < eighths * width / 8
+        }
+        '▀' => y < mid,
+        '▔' => y < height / 8,
+        '─' | '-' => y == mid,
+        '━' | '=' => y == mid || y + 1 == mid,
+        '░' | '.' => return 0.25,
+        '▒' | ':' => return 0.5,
+        '▓' | '*' => return 0.75,
+        glyph @ '\u{2800}'..='\u{28FF}' => {
+            // Each dot is a square in the middle half of its slot.
+            let bits = (glyph as u32 - 0x2800) as u8;
+            let (slot_width, slot_height) = ((width / 2).max(1), (height / 4).max(1));
+            let (dx, dy) = ((x / slot_width).min(1), (y / slot_height).min(3));
+            let (sx, sy) = (x % slot_width, y % slot_height);
+            let middle = |offset: usize, size: usize| (size / 4..size - size / 4).contains(&offset);
+            bits & DOT_BITS[dx][dy] != 0 && middle(sx, slot_width) && middle(sy, slot_height)
+        }
+        _ => false,
+    };
+    if covered { 1.0 } else { 0.0 }
+}
+
+fn mix(from: u8, to: u8, amount: f32) -> u8 {
+    (from as f32 + (to as f32 - from as f32) * amount).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lower_blocks_fill_from_the_bottom() {
+        let cell = |glyph| Cell {
+            glyph,
+            style: Default::default(),
+        };
+        assert_eq!(coverage(&cell('▄'), 0, 15, 8, 16), 1.0);
+        assert_eq!(coverage(&cell('▄'), 0, 0, 8, 16), 0.0);
+        assert_eq!(coverage(&cell(' '), 3, 3, 8, 16), 0.0);
+        assert_eq!(coverage(&cell('█'), 3, 3, 8, 16), 1.0);
+        assert_eq!(coverage(&cell('▌'), 3, 0, 8, 16), 1.0);
+        assert_eq!(coverage(&cell('▌'), 4, 0, 8, 16), 0.0);
+    }
+
+    #[test]
+    fn rasterizes_only_the_rows_asked_for() {
+        let mut frame = Frame::new(crate::ColorDepth::Truecolor);
+        frame.push_str("title");
+        frame.end_row();
+        frame.push('█');
+        frame.end_row();
+
+        let image = rasterize(&frame, 1..2, (2, 3));
+        assert_eq!(image.dimensions(), (2, 3));
+        assert!(image.pixels().all(|rgb| rgb.0 == [0xd0, 0xd0, 0xd0]));
+    }
+}
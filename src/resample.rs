@@ -0,0 +1,180 @@
+//! Converting a stream of samples to another rate, for analysis.
+
+use crate::spectrum::WindowFunction;
+
+/// The rate spectrum analysis runs at, whatever the source, so each FFT bin and
+/// bar covers the same frequencies from file to file.
+pub(crate) const ANALYSIS_RATE: u32 = 44_100;
+
+// Zero crossings of the anti-alias filter's sinc on each side of its centre, in
+// output samples. More make a steeper cutoff at the cost of a longer filter.
+const ZERO_CROSSINGS: usize = 16;
+
+// How much of the new Nyquist frequency the anti-alias filter passes, leaving
+// the rest for its transition band.
+const PASSBAND: f64 = 0.9;
+
+// Linear interpolation between neighbouring samples, carried across buffers.
+// Going down in rate the input is low-passed first with a windowed-sinc filter,
+// so content above the new Nyquist frequency doesn't fold back in; that delays
+// the output by half the filter's length.
+pub(crate) struct Resampler {
+    from: u32,
+    to: u32,
+    // The anti-alias filter, empty when going up in rate.
+    taps: Vec<f32>,
+    // The last input samples before the latest buffer, one fewer than `taps`.
+    history: Vec<f32>,
+    // `history` followed by the latest buffer, and that buffer filtered.
+    padded: Vec<f32>,
+    filtered: Vec<f32>,
+    // Where the next output sample falls, in input samples from the start of
+    // the next buffer. Between -1 and 0 it lies after `last`.
+    position: f64,
+    last: f32,
+}
+
+impl Resampler {
+    pub(crate) fn new(from: u32, to: u32) -> Self {
+        let (from, to) = (from.max(1), to.max(1));
+        let taps = if from > to {
+            low_pass(PASSBAND * 0.5 * to as f64 / from as f64, from.div_ceil(to))
+        } else {
+            Vec::new()
+        };
+        Self {
+            from,
+            to,
+            history: vec![0.0; taps.len().saturating_sub(1)],
+            taps,
+            padded: Vec::new(),
+            filtered: Vec::new(),
+            position: 0.0,
+            last: 0.0,
+        }
+    }
+
+    pub(crate) fn rates(&self) -> (u32, u32) {
+        (self.from, self.to)
+    }
+
+    // Append `input`, continuing from the last buffer, to `out` at the new rate.
+    pub(crate) fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        if input.is_empty() {
+            return;
+        }
+        let mut filtered = std::mem::take(&mut self.filtered);
+        let input = if self.taps.is_empty() {
+            input
+        } else {
+            self.padded.clear();
+            self.padded.extend_from_slice(&self.history);
+            self.padded.extend_from_slice(input);
+            filtered.clear();
+            filtered.extend(self.padded.windows(self.taps.len()).map(|window| {
+                window
+                    .iter()
+                    .zip(&self.taps)
+                    .map(|(sample, tap)| sample * tap)
+                    .sum::<f32>()
+            }));
+            self.history.clear();
+            self.history
+                .extend_from_slice(&self.padded[self.padded.len() - (self.taps.len() - 1)..]);
+            &filtered[..]
+        };
+        let newest = input[input.len() - 1];
+
+        let step = self.from as f64 / self.to as f64;
+        let sample = |i: isize| if i < 0 { self.last } else { input[i as usize] };
+
+        let end = (input.len() - 1) as f64;
+        while self.position < end {
+            let base = self.position.floor();
+            let frac = (self.position - base) as f32;
+            let (a, b) = (sample(base as isize), sample(base as isize + 1));
+            out.push(a + (b - a) * frac);
+            self.position += step;
+        }
+        self.position -= input.len() as f64;
+        self.last = newest;
+        self.filtered = filtered;
+    }
+}
+
+// A Blackman-windowed sinc low-pass with its cutoff at `cutoff` cycles per
+// sample and a gain of 1 at DC, reaching `ZERO_CROSSINGS` output samples of
+// `ratio` input samples each to either side.
+fn low_pass(cutoff: f64, ratio: u32) -> Vec<f32> {
+    let half = ZERO_CROSSINGS * ratio as usize;
+    let window = WindowFunction::Blackman.coefficients(2 * half + 1);
+    let taps: Vec<f64> = window
+        .iter()
+        .enumerate()
+        .map(|(n, &weight)| {
+            let x = 2.0 * cutoff * (n as f64 - half as f64);
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+            };
+            sinc * weight as f64
+        })
+        .collect();
+    let sum: f64 = taps.iter().sum();
+    taps.iter().map(|&tap| (tap / sum) as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The RMS level of a `frequency` Hz sine at `from` Hz brought to `to` Hz,
+    // from buffers of 1000 samples, once the filter has filled up.
+    fn level_after(frequency: f64, from: u32, to: u32) -> f32 {
+        let sine: Vec<f32> = (0..from)
+            .map(|n| (std::f64::consts::TAU * frequency * n as f64 / from as f64).sin() as f32)
+            .collect();
+        let mut resampler = Resampler::new(from, to);
+        let mut out = Vec::new();
+        for buffer in sine.chunks(1000) {
+            resampler.process(buffer, &mut out);
+        }
+        let settled = &out[out.len() / 2..];
+        (settled.iter().map(|x| x * x).sum::<f32>() / settled.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn going_down_in_rate_filters_out_what_would_fold_back() {
+        let full = std::f32::consts::FRAC_1_SQRT_2;
+        // Well inside the new Nyquist frequency of 22.05 kHz, with linear
+        // interpolation dulling the top a little...
+        assert!((level_after(1_000.0, 96_000, 44_100) - full).abs() < 0.01);
+        assert!((level_after(10_000.0, 96_000, 44_100) - full).abs() < 0.05);
+        // ...and past it, where they'd otherwise show up at 20.1 and 10.1 kHz.
+        assert!(level_after(24_000.0, 96_000, 44_100) < 0.001);
+        assert!(level_after(34_000.0, 96_000, 44_100) < 0.001);
+    }
+
+    #[test]
+    fn going_down_in_rate_keeps_a_steady_level() {
+        let mut resampler = Resampler::new(2, 1);
+        let mut out = Vec::new();
+        for _ in 0..20 {
+            resampler.process(&[0.5; 7], &mut out);
+        }
+        // Once the filter has filled up, about half its length in.
+        assert_eq!(out.len(), 70);
+        assert!(out[..10].iter().all(|&x| x < 0.1));
+        assert!(out[40..].iter().all(|&x| (x - 0.5).abs() < 1e-4));
+    }
+
+    #[test]
+    fn doubling_the_rate_interpolates_across_buffers() {
+        let mut resampler = Resampler::new(1, 2);
+        let mut out = Vec::new();
+        resampler.process(&[0.0, 1.0], &mut out);
+        resampler.process(&[2.0], &mut out);
+        assert_eq!(out, [0.0, 0.5, 1.0, 1.5]);
+    }
+}
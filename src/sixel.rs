@@ -0,0 +1,146 @@
+//! Encoding RGB images as Sixel graphics, for terminals that can show them.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// Sixel palettes commonly hold 256 registers.
+const MAX_COLORS: usize = 256;
+
+// Append `pixels`, `width` x `height` RGB, to `out` as one Sixel image drawn
+// at the cursor.
+pub(crate) fn encode(width: usize, height: usize, pixels: &[u8], out: &mut String) {
+    let (palette, indexes) = quantize(pixels);
+
+    // Zero bits leave the screen alone, so the last band's padding below
+    // `height` doesn't paint over the row under the image.
+    out.push_str("\x1BP0;1q");
+    let _ = write!(out, "\"1;1;{};{}", width, height);
+    for (i, &(r, g, b)) in palette.iter().enumerate() {
+        let percent = |v: u8| (v as u32 * 100 + 127) / 255;
+        let _ = write!(out, "#{};2;{};{};{}", i, percent(r), percent(g), percent(b));
+    }
+
+    let mut used = vec![false; palette.len()];
+    for top in (0..height).step_by(6) {
+        let band = top..(top + 6).min(height);
+        used.iter_mut().for_each(|used| *used = false);
+        for y in band.clone() {
+            for &index in &indexes[y * width..(y + 1) * width] {
+                used[index as usize] = true;
+            }
+        }
+
+        let mut first = true;
+        for color in (0..palette.len()).filter(|&color| used[color]) {
+            if !first {
+                // Back to the start of the band for the next color.
+                out.push('$');
+            }
+            first = false;
+            let _ = write!(out, "#{}", color);
+
+            let sixels = (0..width).map(|x| {
+                let bits = band.clone().enumerate().fold(0u8, |bits, (bit, y)| {
+                    let set = indexes[y * width + x] as usize == color;
+                    bits | (u8::from(set) << bit)
+                });
+                (b'?' + bits) as char
+            });
+            push_runs(out, sixels);
+        }
+        out.push('-');
+    }
+    out.push_str("\x1B\\");
+}
+
+// Sixel characters with repeats run-length encoded, dropping a trailing blank
+// run since there's nothing to draw there.
+fn push_runs(out: &mut String, sixels: impl Iterator<Item = char>) {
+    let mut run: Option<(char, usize)> = None;
+    let flush = |out: &mut String, (sixel, count): (char, usize)| match count {
+        1..=3 => (0..count).for_each(|_| out.push(sixel)),
+        _ => {
+            let _ = write!(out, "!{}{}", count, sixel);
+        }
+    };
+    for sixel in sixels {
+        run = match run {
+            Some((current, count)) if current == sixel => Some((current, count + 1)),
+            Some(done) => {
+                flush(out, done);
+                Some((sixel, 1))
+            }
+            None => Some((sixel, 1)),
+        };
+    }
+    if let Some(last) = run.filter(|&(sixel, _)| sixel != '?') {
+        flush(out, last);
+    }
+}
+
+// A palette for `pixels` and each pixel's index into it. Colors are kept
+// exactly when they fit in the palette and rounded to a 6x6x6 cube otherwise.
+fn quantize(pixels: &[u8]) -> (Vec<(u8, u8, u8)>, Vec<u8>) {
+    let mut palette: Vec<(u8, u8, u8)> = Vec::new();
+    let mut lookup = HashMap::new();
+    let mut indexes = Vec::with_capacity(pixels.len() / 3);
+    for rgb in pixels.chunks_exact(3) {
+        let color = (rgb[0], rgb[1], rgb[2]);
+        let index = *lookup.entry(color).or_insert_with(|| {
+            palette.push(color);
+            palette.len() - 1
+        });
+        if palette.len() > MAX_COLORS {
+            return cube(pixels);
+        }
+        indexes.push(index as u8);
+    }
+    (palette, indexes)
+}
+
+fn cube(pixels: &[u8]) -> (Vec<(u8, u8, u8)>, Vec<u8>) {
+    let level = |v: u8| (v as u32 * 5 + 127) / 255;
+    let palette = (0..216u32)
+        .map(|i| {
+            let value = |l: u32| (l * 255 / 5) as u8;
+            (value(i / 36), value(i / 6 % 6), value(i % 6))
+        })
+        .collect();
+    let indexes = pixels
+        .chunks_exact(3)
+        .map(|rgb| (level(rgb[0]) * 36 + level(rgb[1]) * 6 + level(rgb[2])) as u8)
+        .collect();
+    (palette, indexes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_solid_band_is_one_run() {
+        let pixels = [255, 0, 0].repeat(10 * 6);
+        let mut out = String::new();
+        encode(10, 6, &pixels, &mut out);
+        assert_eq!(out, "\x1BP0;1q\"1;1;10;6#0;2;100;0;0#0!10~-\x1B\\");
+    }
+
+    #[test]
+    fn each_color_gets_its_own_pass_over_the_band() {
+        // Red above black, two pixels wide and two tall.
+        let pixels = [255, 0, 0, 255, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut out = String::new();
+        encode(2, 2, &pixels, &mut out);
+        assert!(out.ends_with("#0@@$#1AA-\x1B\\"), "{:?}", out);
+    }
+
+    #[test]
+    fn too_many_colors_fall_back_to_a_cube() {
+        let pixels: Vec<u8> = (0..300u32)
+            .flat_map(|i| [i as u8, (i / 256) as u8, 0])
+            .collect();
+        let (palette, indexes) = quantize(&pixels);
+        assert_eq!(palette.len(), 216);
+        assert_eq!(indexes.len(), 300);
+    }
+}
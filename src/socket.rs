@@ -0,0 +1,114 @@
+//! Sending bar levels to another process through a Unix domain socket or a
+//! named pipe, alongside drawing them.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::emit::{EmitFormat, LevelWriter};
+
+// Frames queued for a slow consumer before new ones are dropped.
+const QUEUE: usize = 8;
+
+// How long to wait after a failed connection before trying again.
+const RETRY: Duration = Duration::from_secs(1);
+
+// Hands each frame's levels to a thread that writes them to `path` as JSON
+// lines, so a consumer that's slow, missing or gone never holds up drawing.
+// Frames sent while nobody is listening are dropped.
+pub(crate) struct LevelSocket {
+    frames: SyncSender<Vec<u8>>,
+    writer: LevelWriter,
+    line: Vec<u8>,
+}
+
+impl LevelSocket {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        let (frames, queued) = mpsc::sync_channel(QUEUE);
+        thread::spawn(move || forward(&path, queued));
+        Self {
+            frames,
+            writer: LevelWriter::new(EmitFormat::Json),
+            line: Vec::new(),
+        }
+    }
+
+    pub(crate) fn send(&mut self, time: Option<Duration>, levels: &[(f32, f32)]) {
+        self.line.clear();
+        if self.writer.write(&mut self.line, time, levels).is_ok() {
+            // A full queue means the consumer is behind; it misses this frame.
+            let _ = self.frames.try_send(self.line.clone());
+        }
+    }
+}
+
+// Write frames from `queued` to `path` until the visualizer goes away,
+// reconnecting whenever the consumer disconnects.
+fn forward(path: &Path, queued: Receiver<Vec<u8>>) {
+    let mut consumer = None;
+    let mut last_attempt: Option<Instant> = None;
+    for line in queued {
+        if consumer.is_none() && last_attempt.is_none_or(|at| at.elapsed() >= RETRY) {
+            last_attempt = Some(Instant::now());
+            match connect(path) {
+                Ok(connected) => {
+                    debug!("sending levels to {}", path.display());
+                    consumer = Some(connected);
+                }
+                Err(err) => debug!("{}: {}", path.display(), err),
+            }
+        }
+        if let Some(out) = &mut consumer
+            && let Err(err) = out.write_all(&line)
+        {
+            debug!("{}: consumer went away: {}", path.display(), err);
+            consumer = None;
+        }
+    }
+}
+
+// A socket is connected to; anything else, a named pipe most likely, is opened
+// for writing. Opening a pipe waits until something opens it for reading.
+fn connect(path: &Path) -> io::Result<Box<dyn Write>> {
+    if fs::metadata(path)?.file_type().is_socket() {
+        Ok(Box::new(UnixStream::connect(path)?))
+    } else {
+        Ok(Box::new(OpenOptions::new().write(true).open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixListener;
+
+    #[test]
+    fn levels_reach_a_listening_socket_and_survive_a_disconnect() {
+        let path = std::env::temp_dir().join(format!("echoes-socket-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let mut socket = LevelSocket::new(path.clone());
+        socket.send(Some(Duration::from_millis(500)), &[(0.5, 0.25)]);
+        let (stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        assert_eq!(line, "{\"time\":0.500,\"levels\":[0.5000]}\n");
+
+        // Sending on after the consumer hangs up is quietly dropped.
+        drop(stream);
+        for _ in 0..QUEUE * 2 {
+            socket.send(None, &[(1.0, 1.0)]);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
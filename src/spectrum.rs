@@ -0,0 +1,255 @@
+//! Rolling FFT analysis for the spectrum mode.
+
+use std::sync::Arc;
+
+use clap::ValueEnum;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use crate::resample::{ANALYSIS_RATE, Resampler};
+
+const FFT_SIZE: usize = 2048;
+
+/// Taper applied to each FFT frame. Wider main lobes blur neighbouring
+/// frequencies together but leak less energy into far-away bins.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum WindowFunction {
+    /// No taper: sharpest peaks, worst leakage.
+    Rectangular,
+    #[default]
+    Hann,
+    Hamming,
+    /// Lowest sidelobes, widest peaks.
+    Blackman,
+}
+
+impl WindowFunction {
+    // Window coefficients for a frame of `len` samples.
+    pub(crate) fn coefficients(self, len: usize) -> Vec<f32> {
+        use std::f32::consts::TAU;
+
+        let denom = len.saturating_sub(1).max(1) as f32;
+        (0..len)
+            .map(|n| {
+                let phase = TAU * n as f32 / denom;
+                match self {
+                    WindowFunction::Rectangular => 1.0,
+                    WindowFunction::Hann => 0.5 - 0.5 * phase.cos(),
+                    WindowFunction::Hamming => 0.54 - 0.46 * phase.cos(),
+                    WindowFunction::Blackman => {
+                        0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos()
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Frequency weighting applied to bin magnitudes before they're grouped into
+/// bars. Raw magnitudes make the bass look louder than it sounds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Weighting {
+    /// Raw magnitudes.
+    #[default]
+    None,
+    /// The IEC 61672 A-weighting curve, for roughly how loud each band sounds.
+    A,
+    /// A +3 dB per octave tilt about 1 kHz, so pink noise reads flat.
+    Pink,
+}
+
+impl Weighting {
+    // Amplitude gain at `frequency` Hz.
+    fn gain(self, frequency: f32) -> f32 {
+        match self {
+            Weighting::None => 1.0,
+            Weighting::A => {
+                let f2 = frequency * frequency;
+                let response = 12194.0f32.powi(2) * f2 * f2
+                    / ((f2 + 20.6f32.powi(2))
+                        * ((f2 + 107.7f32.powi(2)) * (f2 + 737.9f32.powi(2))).sqrt()
+                        * (f2 + 12194.0f32.powi(2)));
+                // Normalized to 0 dB at 1 kHz.
+                response * 10.0f32.powf(2.0 / 20.0)
+            }
+            Weighting::Pink => (frequency / 1000.0).sqrt(),
+        }
+    }
+
+    // Gains for each of the `bins` lowest bins of an `FFT_SIZE` frame at `rate`.
+    fn bin_gains(self, bins: usize, rate: u32) -> Vec<f32> {
+        (0..bins)
+            .map(|bin| self.gain(bin as f32 * rate as f32 / FFT_SIZE as f32))
+            .collect()
+    }
+}
+
+// Rolling FFT over the most recent `FFT_SIZE` samples.
+pub(crate) struct Spectrum {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    // Converts a bin's magnitude back to the amplitude of the sine that produced it.
+    scale: f32,
+    // Per-bin gains for the weighting, taken at `rate`, or `None` for raw
+    // magnitudes.
+    weighting: Weighting,
+    gains: Option<Vec<f32>>,
+    // The sample rate the bins are analyzed at: `ANALYSIS_RATE` unless
+    // resampling is off.
+    rate: u32,
+    history: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    // Brings sources at other rates to `ANALYSIS_RATE`, with the samples it
+    // produced for the latest buffer.
+    resampler: Option<Resampler>,
+    resampled: Vec<f32>,
+}
+
+impl Spectrum {
+    pub(crate) fn new(window: WindowFunction) -> Self {
+        let window = window.coefficients(FFT_SIZE);
+        let scale = 2.0 / window.iter().sum::<f32>();
+
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            scale,
+            weighting: Weighting::None,
+            gains: None,
+            rate: ANALYSIS_RATE,
+            history: vec![0.0; FFT_SIZE],
+            buffer: vec![Complex::default(); FFT_SIZE],
+            resampler: None,
+            resampled: Vec::new(),
+        }
+    }
+
+    pub(crate) fn weighting(&self) -> Weighting {
+        self.weighting
+    }
+
+    pub(crate) fn set_weighting(&mut self, weighting: Weighting) {
+        self.weighting = weighting;
+        self.gains =
+            (weighting != Weighting::None).then(|| weighting.bin_gains(FFT_SIZE / 2, self.rate));
+    }
+
+    // Lay the weighting over bins analyzed at `rate` from now on.
+    pub(crate) fn set_rate(&mut self, rate: u32) {
+        if rate != self.rate {
+            self.rate = rate;
+            self.set_weighting(self.weighting);
+        }
+    }
+
+    // Append magnitudes for `num_bars` log-spaced bands to `columns`, mirrored
+    // about the centerline. Samples at a known `rate` other than `ANALYSIS_RATE`
+    // are resampled to it first.
+    pub(crate) fn columns(
+        &mut self,
+        samples: &[f32],
+        rate: Option<u32>,
+        num_bars: usize,
+        columns: &mut Vec<(f32, f32)>,
+    ) {
+        let mut resampled = std::mem::take(&mut self.resampled);
+        let samples = match rate.filter(|&rate| rate != ANALYSIS_RATE) {
+            Some(rate) => {
+                let resampler = match self.resampler.take() {
+                    Some(resampler) if resampler.rates() == (rate, ANALYSIS_RATE) => resampler,
+                    _ => Resampler::new(rate, ANALYSIS_RATE),
+                };
+                resampled.clear();
+                self.resampler
+                    .insert(resampler)
+                    .process(samples, &mut resampled);
+                &resampled[..]
+            }
+            None => samples,
+        };
+
+        let fresh = &samples[samples.len().saturating_sub(FFT_SIZE)..];
+        self.history.drain(..fresh.len());
+        self.history.extend_from_slice(fresh);
+
+        for ((slot, &sample), &weight) in
+            self.buffer.iter_mut().zip(&self.history).zip(&self.window)
+        {
+            *slot = Complex::new(sample * weight, 0.0);
+        }
+        self.fft.process(&mut self.buffer);
+
+        let bins = FFT_SIZE / 2;
+
+        columns.extend((0..num_bars).map(|i| {
+            let (lo, hi) = log_band(i, num_bars, bins);
+            let band = &self.buffer[lo..hi];
+            let magnitude = match &self.gains {
+                Some(gains) => band
+                    .iter()
+                    .zip(&gains[lo..hi])
+                    .fold(0.0f32, |acc, (bin, gain)| acc.max(bin.norm() * gain)),
+                None => band.iter().fold(0.0f32, |acc, bin| acc.max(bin.norm())),
+            } * self.scale;
+            (magnitude, magnitude)
+        }));
+        self.resampled = resampled;
+    }
+}
+
+// The bar of `num_bars` whose band holds `frequency`, for a spectrum analyzed
+// at `rate`, or `None` when it falls outside every band.
+pub(crate) fn bar_for_frequency(frequency: f32, rate: u32, num_bars: usize) -> Option<usize> {
+    let bins = FFT_SIZE / 2;
+    let bin = (frequency * FFT_SIZE as f32 / rate.max(1) as f32) as usize;
+    if !(1..bins).contains(&bin) {
+        return None;
+    }
+    (0..num_bars).find(|&i| {
+        let (lo, hi) = log_band(i, num_bars, bins);
+        (lo..hi).contains(&bin)
+    })
+}
+
+// FFT bins `lo..hi` covered by bar `i`, spaced logarithmically between bin 1 and `bins`.
+// Low bars may share a bin when there are more bars than bass bins to go around.
+fn log_band(i: usize, num_bars: usize, bins: usize) -> (usize, usize) {
+    let edge = |k: usize| (bins as f32).powf(k as f32 / num_bars as f32) as usize;
+    let lo = edge(i).clamp(1, bins - 1);
+    let hi = edge(i + 1).clamp(lo + 1, bins);
+    (lo, hi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weightings_follow_their_curves() {
+        let db = |weighting: Weighting, frequency: f32| 20.0 * weighting.gain(frequency).log10();
+        // IEC 61672 table values, to a tenth of a decibel.
+        for (frequency, expected) in [(100.0, -19.1), (1000.0, 0.0), (10_000.0, -2.5)] {
+            assert!(
+                (db(Weighting::A, frequency) - expected).abs() < 0.1,
+                "{} Hz",
+                frequency
+            );
+        }
+        assert!((db(Weighting::Pink, 2000.0) - 3.0).abs() < 0.05);
+        assert!((db(Weighting::Pink, 250.0) + 6.0).abs() < 0.05);
+        assert_eq!(Weighting::None.gain(50.0), 1.0);
+    }
+
+    #[test]
+    fn weighting_follows_the_analysis_rate() {
+        let mut spectrum = Spectrum::new(WindowFunction::default());
+        spectrum.set_weighting(Weighting::A);
+        let at_44k = spectrum.gains.clone().unwrap();
+
+        // At twice the rate every bin is twice the frequency.
+        spectrum.set_rate(2 * ANALYSIS_RATE);
+        let at_88k = spectrum.gains.as_ref().unwrap();
+        assert_eq!(at_88k[100], at_44k[200]);
+        assert_ne!(at_88k[100], at_44k[100]);
+    }
+}
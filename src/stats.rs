@@ -0,0 +1,91 @@
+//! Timing how long each frame spends decoding, analyzing and drawing, for a
+//! status line under the visualization.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// How much of each new frame's timing goes into the moving averages: about the
+// last 30 frames count.
+const WEIGHT: f64 = 1.0 / 30.0;
+
+// Moving averages of the time per frame spent in each phase, and when the
+// frames of the last second were drawn.
+#[derive(Default)]
+pub(crate) struct FrameStats {
+    decode: f64,
+    analyze: f64,
+    draw: f64,
+    // Decoding done since the last frame, which may span several buffers.
+    decoding: Duration,
+    frames: VecDeque<Instant>,
+}
+
+impl FrameStats {
+    pub(crate) fn add_decode(&mut self, time: Duration) {
+        self.decoding += time;
+    }
+
+    // Count a frame drawn just now, which took `analyze` and `draw`.
+    pub(crate) fn frame(&mut self, analyze: Duration, draw: Duration) {
+        let now = Instant::now();
+        let first = self.frames.is_empty();
+        let decoding = std::mem::take(&mut self.decoding);
+        for (average, time) in [
+            (&mut self.decode, decoding),
+            (&mut self.analyze, analyze),
+            (&mut self.draw, draw),
+        ] {
+            let time = time.as_secs_f64();
+            *average = if first {
+                time
+            } else {
+                *average + (time - *average) * WEIGHT
+            };
+        }
+
+        self.frames.push_back(now);
+        while self
+            .frames
+            .front()
+            .is_some_and(|&at| now.duration_since(at) > Duration::from_secs(1))
+        {
+            self.frames.pop_front();
+        }
+    }
+
+    // Frames over the last second and milliseconds per phase, as one line.
+    pub(crate) fn line(&self) -> String {
+        let ms = |seconds: f64| seconds * 1000.0;
+        format!(
+            "{} fps  decode {:.2} ms  analyze {:.2} ms  draw {:.2} ms",
+            self.frames.len(),
+            ms(self.decode),
+            ms(self.analyze),
+            ms(self.draw)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_average_over_recent_frames() {
+        let mut stats = FrameStats::default();
+        stats.add_decode(Duration::from_millis(1));
+        stats.add_decode(Duration::from_millis(1));
+        stats.frame(Duration::from_millis(3), Duration::from_millis(4));
+        assert_eq!(
+            stats.line(),
+            "1 fps  decode 2.00 ms  analyze 3.00 ms  draw 4.00 ms"
+        );
+
+        // Later frames move the averages only part of the way.
+        stats.frame(Duration::from_millis(33), Duration::ZERO);
+        assert_eq!(
+            stats.line(),
+            "2 fps  decode 1.93 ms  analyze 4.00 ms  draw 3.87 ms"
+        );
+    }
+}
@@ -0,0 +1,311 @@
+//! Terminal setup, size queries and keyboard input.
+
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use log::warn;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+
+/// The stream frames are drawn on, which is the one measured, colored for and
+/// taken over by [`ScreenGuard`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Screen {
+    /// Standard output.
+    #[default]
+    Stdout,
+    /// Standard error, so stdout is left free for other output, though log
+    /// messages share the screen.
+    Stderr,
+}
+
+// Set by `Screen::select`; stdout until then.
+static ON_STDERR: AtomicBool = AtomicBool::new(false);
+
+impl Screen {
+    /// Draw on this stream from now on. Call it before building a
+    /// [`Visualizer`](crate::Visualizer), which decides on color by it.
+    pub fn select(self) {
+        ON_STDERR.store(self == Screen::Stderr, Ordering::Relaxed);
+    }
+
+    /// The stream last [`select`](Self::select)ed.
+    pub fn current() -> Self {
+        if ON_STDERR.load(Ordering::Relaxed) {
+            Screen::Stderr
+        } else {
+            Screen::Stdout
+        }
+    }
+
+    pub fn is_terminal(self) -> bool {
+        match self {
+            Screen::Stdout => io::stdout().is_terminal(),
+            Screen::Stderr => io::stderr().is_terminal(),
+        }
+    }
+
+    /// A handle to write frames to.
+    pub fn writer(self) -> Box<dyn Write + Send> {
+        match self {
+            Screen::Stdout => Box::new(io::stdout()),
+            Screen::Stderr => Box::new(io::stderr()),
+        }
+    }
+}
+
+/// Whether the locale promises UTF-8 output, going by the first of `LC_ALL`,
+/// `LC_CTYPE` and `LANG` that is set. An unset locale counts as plain ASCII.
+pub fn unicode_locale() -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .is_some_and(|locale| {
+            let locale = locale.to_ascii_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+}
+
+// Primary device attributes: every terminal answers `ESC [ ? attrs c`.
+const DA1: &str = "\x1B[c";
+// How long to wait for the terminal to answer a query.
+const QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Whether the terminal shows Sixel graphics. Terminals known by `$TERM` or
+/// `$TERM_PROGRAM` count straight away; otherwise the terminal is asked for its
+/// device attributes, which include 4 when Sixel is on, as in xterm started
+/// with `-ti vt340`.
+pub fn sixel_supported() -> bool {
+    const TERMS: [&str; 6] = ["mlterm", "foot", "contour", "yaft", "sixel", "wezterm"];
+    const PROGRAMS: [&str; 4] = ["WezTerm", "iTerm.app", "mintty", "konsole"];
+
+    let var = |name| std::env::var(name).unwrap_or_default();
+    let term = var("TERM").to_ascii_lowercase();
+    TERMS.iter().any(|name| term.contains(name))
+        || PROGRAMS.contains(&var("TERM_PROGRAM").as_str())
+        || query(DA1).is_some_and(|reply| reports_sixel(&reply))
+}
+
+/// Whether the terminal speaks the Kitty graphics protocol, asked with the
+/// protocol's own query. If the terminal can't be asked, this goes by the
+/// variables Kitty sets and by `$TERM` and `$TERM_PROGRAM`.
+pub fn kitty_supported() -> bool {
+    // Query a one-pixel RGB image without storing it; device attributes follow
+    // so terminals that ignore the query still answer something.
+    const QUERY: &str = "\x1B_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1B\\";
+
+    if let Some(reply) = query(&format!("{}{}", QUERY, DA1)) {
+        return reply.contains("\x1B_Gi=31;OK");
+    }
+    let var = |name| std::env::var(name).unwrap_or_default();
+    let term = var("TERM").to_ascii_lowercase();
+    std::env::var_os("KITTY_WINDOW_ID").is_some()
+        || term.contains("kitty")
+        || term.contains("ghostty")
+        || ["ghostty", "WezTerm"].contains(&var("TERM_PROGRAM").as_str())
+}
+
+// Send `query`, which must end by asking for the device attributes, to the
+// terminal in raw mode, and collect what comes back up to the end of their
+// report. `None` when the screen isn't a terminal or it doesn't answer in time.
+fn query(query: &str) -> Option<String> {
+    if !Screen::current().is_terminal() {
+        return None;
+    }
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    let mut input = tty.try_clone().ok()?;
+
+    let was_raw = crossterm::terminal::is_raw_mode_enabled().unwrap_or(false);
+    if !was_raw && crossterm::terminal::enable_raw_mode().is_err() {
+        return None;
+    }
+    // The read blocks, so it runs on its own thread, passing each byte on. One
+    // still waiting after the timeout stops at the next byte the terminal
+    // sends, finding nobody to pass it to.
+    let (sender, bytes) = mpsc::channel();
+    let sent = tty.write_all(query.as_bytes()).and_then(|()| tty.flush());
+    if sent.is_ok() {
+        std::thread::spawn(move || {
+            let mut reply = Vec::new();
+            let mut byte = [0];
+            while !ends_attributes(&reply) && matches!(input.read(&mut byte), Ok(1)) {
+                reply.push(byte[0]);
+                if sender.send(byte[0]).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut reply = Vec::new();
+    while !ends_attributes(&reply) {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match bytes.recv_timeout(left) {
+            Ok(byte) => reply.push(byte),
+            Err(_) => break,
+        }
+    }
+    if !was_raw {
+        let _ = crossterm::terminal::disable_raw_mode();
+    }
+    ends_attributes(&reply).then(|| String::from_utf8_lossy(&reply).into_owned())
+}
+
+// Whether `reply` ends with a complete device attributes report.
+fn ends_attributes(reply: &[u8]) -> bool {
+    reply.ends_with(b"c")
+        && reply
+            .windows(3)
+            .rposition(|start| start == b"\x1B[?")
+            .is_some_and(|start| {
+                reply[start + 3..reply.len() - 1]
+                    .iter()
+                    .all(|&byte| byte.is_ascii_digit() || byte == b';')
+            })
+}
+
+// Whether the device attributes in `reply` list Sixel graphics, attribute 4.
+fn reports_sixel(reply: &str) -> bool {
+    reply
+        .rsplit_once("\x1B[?")
+        .and_then(|(_, attributes)| attributes.strip_suffix('c'))
+        .is_some_and(|attributes| attributes.split(';').skip(1).any(|attr| attr == "4"))
+}
+
+/// Holds the terminal on the alternate screen with the cursor hidden, putting
+/// everything back when dropped or when the program panics.
+pub struct ScreenGuard {
+    active: bool,
+}
+
+impl ScreenGuard {
+    /// Take over the screen if the [`Screen`] is a terminal. `raw_input` also
+    /// switches to raw mode so single key presses arrive unbuffered.
+    pub fn enter(raw_input: bool) -> Self {
+        let active = Screen::current().is_terminal();
+        if active {
+            let default_hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                restore_screen();
+                default_hook(info);
+            }));
+
+            if raw_input && let Err(err) = crossterm::terminal::enable_raw_mode() {
+                warn!("keyboard controls unavailable: {}", err);
+            }
+            let mut screen = Screen::current().writer();
+            let _ = screen.write_all(b"\x1B[?1049h\x1B[?25l");
+            let _ = screen.flush();
+        }
+
+        Self { active }
+    }
+}
+
+impl Drop for ScreenGuard {
+    fn drop(&mut self) {
+        if self.active {
+            restore_screen();
+        }
+    }
+}
+
+// Show the cursor and leave the alternate screen. Safe to call more than once.
+fn restore_screen() {
+    let _ = crossterm::terminal::disable_raw_mode();
+    let mut screen = Screen::current().writer();
+    let _ = screen.write_all(b"\x1B[0m\x1B[?25h\x1B[?1049l");
+    let _ = screen.flush();
+}
+
+// Bar count and height that fill the terminal, or `None` when not on a TTY.
+pub(crate) fn terminal_layout() -> Option<(usize, usize)> {
+    if !Screen::current().is_terminal() {
+        return None;
+    }
+
+    let (cols, rows) = crossterm::terminal::size().ok()?;
+    // A zero size means the terminal didn't say.
+    if cols == 0 || rows == 0 {
+        return None;
+    }
+    // Leave the last row free so the trailing newline doesn't scroll the frame.
+    let height = rows as usize - 1;
+
+    Some((cols as usize, height))
+}
+
+// Pixels per character cell, when the terminal reports its size in pixels.
+pub(crate) fn cell_pixels() -> Option<(usize, usize)> {
+    let size = crossterm::terminal::window_size().ok()?;
+    if size.columns == 0 || size.rows == 0 || size.width == 0 || size.height == 0 {
+        return None;
+    }
+    Some((
+        (size.width / size.columns) as usize,
+        (size.height / size.rows) as usize,
+    ))
+}
+
+/// A key press understood by the player.
+pub(crate) enum Command {
+    TogglePause,
+    NextMode,
+    SeekBackward,
+    SeekForward,
+    Quit,
+}
+
+// Key presses waiting on the terminal, without blocking.
+pub(crate) fn read_commands() -> Vec<Command> {
+    let mut commands = Vec::new();
+
+    while event::poll(Duration::ZERO).unwrap_or(false) {
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(_) => break,
+        };
+
+        match key.code {
+            // Raw mode swallows SIGINT, so Ctrl+C arrives here as a key.
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                crate::interrupt()
+            }
+            KeyCode::Char(' ') => commands.push(Command::TogglePause),
+            KeyCode::Char('m') => commands.push(Command::NextMode),
+            KeyCode::Left => commands.push(Command::SeekBackward),
+            KeyCode::Right => commands.push(Command::SeekForward),
+            KeyCode::Char('q') | KeyCode::Esc => commands.push(Command::Quit),
+            _ => {}
+        }
+    }
+
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_attributes_are_read_to_their_end() {
+        assert!(ends_attributes(b"\x1B[?62;4;22c"));
+        assert!(ends_attributes(b"\x1B_Gi=31;OK\x1B\\\x1B[?62;c"));
+        assert!(!ends_attributes(b"\x1B[?62;4"));
+        assert!(!ends_attributes(b"\x1B_Gi=31;c"));
+
+        assert!(reports_sixel("\x1B[?62;4;22c"));
+        // The first number is the terminal class, not an attribute.
+        assert!(!reports_sixel("\x1B[?4;6c"));
+        assert!(!reports_sixel("\x1B[?62;22;44c"));
+    }
+}
@@ -0,0 +1,2860 @@
+//! Turning samples into bar levels and bar levels into frames.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+
+use crate::analysis::{LevelMode, ease, mirror, rms, scope_columns, waveform_columns};
+use crate::braille::BrailleCanvas;
+use crate::color::{ColorDepth, Theme, blend};
+use crate::config::VisualizerConfig;
+use crate::emit::{EmitFormat, LevelWriter};
+use crate::frame::{Frame, Style};
+use crate::loudness::{LoudnessMeter, Normalization};
+use crate::png::PngOutput;
+use crate::raster::rasterize;
+use crate::resample::ANALYSIS_RATE;
+#[cfg(unix)]
+use crate::socket::LevelSocket;
+use crate::spectrum::{Spectrum, Weighting, WindowFunction, bar_for_frequency};
+use crate::stats::FrameStats;
+use crate::terminal::{Screen, cell_pixels, terminal_layout};
+use crate::{kitty, sixel};
+
+// Layout used when the screen isn't a terminal we can measure.
+pub(crate) const DEFAULT_BARS: usize = 64;
+pub(crate) const DEFAULT_HEIGHT: usize = 21;
+// Seconds of audio the clip marker stays up after a sample hits full scale.
+const CLIP_HOLD: f32 = 1.0;
+// How far past the edge of a palette band, in band widths, a bar's level has
+// to move before the bar changes color.
+const BAND_MARGIN: f32 = 0.15;
+// Exponent bending levels onto the theme's gradient.
+const COLOR_CURVE: f32 = 0.6;
+// Samples `feed` gathers for each frame by default: one spectrum window.
+const FEED_SAMPLES: usize = 2048;
+// The running peak to start from, before any audio has set it.
+const INITIAL_PEAK: f32 = 0.25;
+// Frames a second that the auto-gain's decay and the peak holds' fall are
+// given for; at other frame rates they're scaled to fall as fast in time.
+const REFERENCE_FPS: f32 = 30.0;
+// The smallest terminal a frame is drawn in: this many columns, and rows for
+// a bar above and below the centerline besides the header and other lines.
+const MIN_COLS: usize = 8;
+const MIN_PLOT_ROWS: usize = 3;
+
+/// How incoming samples are turned into bar levels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum VisualizerMode {
+    /// Time-domain amplitude, split above and below the centerline by sign.
+    #[default]
+    Waveform,
+    /// Frequency-domain magnitudes in logarithmically spaced bands.
+    Spectrum,
+    /// A scrolling spectrogram: each frame's spectrum enters as the top row and
+    /// older ones move down, with color and shading showing magnitude.
+    Waterfall,
+    /// An oscilloscope trace: one sample per column, joined into a line.
+    Scope,
+    /// Spectrum bars reflected about the middle: bass in the center, treble
+    /// fanning out to both edges.
+    Mirror,
+    /// Spectrum bars as spokes radiating from the center of the screen, bass at
+    /// the top and treble going round clockwise.
+    Radial,
+    /// A level meter: one horizontal bar per channel, filled to the RMS level
+    /// in decibels below full scale rather than against the auto-gain, with a
+    /// peak-hold tick.
+    Vu,
+    /// Spectrum bars in the upper half of the screen over waveform bars in the
+    /// lower half, each analyzed and drawn as in its own mode.
+    Split,
+}
+
+/// How bar levels ease from one frame to the next.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum EnvelopeMode {
+    /// The same blend of new and old levels going up as going down.
+    #[default]
+    Linear,
+    /// Separate rise and fall times, like a hardware meter: quick to jump to a
+    /// transient, slow to let it go.
+    AttackRelease,
+}
+
+/// What runs along the middle of the bar and scope modes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Centerline {
+    /// `─`, or `-` in ASCII mode.
+    #[default]
+    Line,
+    /// This glyph in every cell of the line.
+    Glyph(char),
+    /// No line. The bar modes then draw upward, as with
+    /// [`Visualizer::with_upward`].
+    Hidden,
+}
+
+impl FromStr for Centerline {
+    type Err = String;
+
+    /// `none` for [`Centerline::Hidden`], or a single character to draw with.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            _ if text.eq_ignore_ascii_case("none") => Ok(Centerline::Hidden),
+            (Some(glyph), None) => Ok(Centerline::Glyph(glyph)),
+            _ => Err("expected a single character or `none`".to_string()),
+        }
+    }
+}
+
+impl VisualizerMode {
+    /// The mode after this one when cycling with `m`.
+    pub fn next(self) -> Self {
+        match self {
+            VisualizerMode::Waveform => VisualizerMode::Spectrum,
+            VisualizerMode::Spectrum => VisualizerMode::Waterfall,
+            VisualizerMode::Waterfall => VisualizerMode::Scope,
+            VisualizerMode::Scope => VisualizerMode::Mirror,
+            VisualizerMode::Mirror => VisualizerMode::Radial,
+            VisualizerMode::Radial => VisualizerMode::Vu,
+            VisualizerMode::Vu => VisualizerMode::Split,
+            VisualizerMode::Split => VisualizerMode::Waveform,
+        }
+    }
+}
+
+// The halves of split mode, top to bottom.
+const SPLIT_UPPER: VisualizerMode = VisualizerMode::Spectrum;
+const SPLIT_LOWER: VisualizerMode = VisualizerMode::Waveform;
+
+// What the lower half of split mode carries from frame to frame. The upper
+// half's lives in the visualizer's own fields, like any other mode's, and the
+// two are swapped whenever the lower half is analyzed or drawn.
+struct View {
+    levels: Vec<(f32, f32)>,
+    prev_columns: Vec<(f32, f32)>,
+    peak_hold: Vec<(f32, f32)>,
+    color_bands: Vec<(usize, usize)>,
+    color_levels: Vec<(f32, f32)>,
+    peak: f32,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            levels: Vec::new(),
+            prev_columns: Vec::new(),
+            peak_hold: Vec::new(),
+            color_bands: Vec::new(),
+            color_levels: Vec::new(),
+            peak: INITIAL_PEAK,
+        }
+    }
+}
+
+/// Turns buffers of samples into frames of colored bars.
+pub struct Visualizer {
+    mode: VisualizerMode,
+    theme: Theme,
+    color_depth: ColorDepth,
+    // Every color is scaled by this, 0.0 to 1.0.
+    brightness: f32,
+    // False when NO_COLOR is set or the screen isn't a terminal.
+    color: bool,
+    num_bars: usize,
+    max_height: usize,
+    // Terminal columns, for fitting the header line.
+    width: usize,
+    // Shown on a fixed line above the bars, e.g. the track's title.
+    header: Option<String>,
+    // Elapsed and total playing time, shown on a line below the bars.
+    progress: Option<(Duration, Option<Duration>)>,
+    // Show each channel's peak in dBFS on a line under the visualization.
+    readout: bool,
+    // Label round frequencies on a line under spectrum bars.
+    freq_labels: bool,
+    // Time spent per frame, shown on a line under the bars when kept.
+    stats: Option<FrameStats>,
+    // Largest magnitude in each channel of the last buffer.
+    channel_peaks: Vec<f32>,
+    // Seconds of audio left to show the clip marker for.
+    clip_hold: f32,
+    // What's on screen now, so the next frame only redraws cells that changed.
+    last_frame: Option<Frame>,
+    // The frame before that, kept to be cleared and drawn into instead of
+    // allocating a new one, and the buffer escapes are built in.
+    spare_frame: Option<Frame>,
+    escapes: String,
+    // Samples handed to `feed` since its last frame, and how many make one.
+    fed: Vec<f32>,
+    feed_samples: usize,
+    // Where frames go as images instead of terminal output.
+    png: Option<PngOutput>,
+    // Levels written as text in place of frames.
+    emit: Option<LevelWriter>,
+    // Levels sent to another process as well, whatever else is drawn.
+    #[cfg(unix)]
+    socket: Option<LevelSocket>,
+    // Draw the visualization rows as an image rather than as characters.
+    graphics: Option<Graphics>,
+    // Draw bar tips with eighth blocks rather than rounding to whole cells.
+    partial_blocks: bool,
+    // Draw bar tips in half blocks, stepping down beside taller neighbours.
+    smooth_edges: bool,
+    // Draw with `#` and `-` for terminals without block and box-drawing glyphs.
+    ascii: bool,
+    // Draw on a Braille dot canvas at 2x4 dots per cell.
+    braille: bool,
+    centerline: Centerline,
+    // Bars up from the bottom over the full height instead of out from the middle.
+    upward: bool,
+    // Mirror the bar and scope modes top to bottom.
+    flip: bool,
+    // Map levels through decibels down to this floor instead of linearly.
+    db_floor: Option<f32>,
+    // Exponent applied to bar heights after that mapping.
+    gamma: f32,
+    // The bar count the caller fixed, if any, which a narrower terminal still
+    // cuts down; `None` follows the terminal width.
+    fixed_bars: Option<usize>,
+    // Likewise for the number of rows.
+    fixed_height: Option<usize>,
+    // The terminal is too small to draw in, as of the last frame.
+    too_small: bool,
+    peak: f32,
+    // A fixed multiplier from levels to heights in place of the auto-gain.
+    gain: Option<f32>,
+    normalization: Normalization,
+    // Rate of the samples being rendered, if known; the loudness meter needs it.
+    sample_rate: Option<u32>,
+    // Bring the spectrum modes' input to a fixed rate first.
+    resample: bool,
+    loudness: Option<LoudnessMeter>,
+    // How waveform bars measure their chunk of samples.
+    level_mode: LevelMode,
+    // Raw levels below this amplitude are drawn as silence.
+    gate: f32,
+    // How much of the running peak carries over each frame as audio gets quieter.
+    decay: f32,
+    // Weight of the new frame against the previous one when easing bar levels,
+    // or a time constant to work the weight out from for each frame.
+    smoothing: f32,
+    smoothing_time: Option<Duration>,
+    // Or, with `EnvelopeMode::AttackRelease`, the time constants for rising and
+    // falling levels.
+    envelope: EnvelopeMode,
+    attack: Duration,
+    release: Duration,
+    // Raw and normalized levels of the frame being drawn, reused across frames.
+    columns: Vec<(f32, f32)>,
+    levels: Vec<(f32, f32)>,
+    // Spectrum bands before mirror mode lays them out.
+    bands: Vec<(f32, f32)>,
+    prev_columns: Vec<(f32, f32)>,
+    // Recent per-bar peaks, falling back toward the bars a little each frame.
+    peak_hold: Vec<(f32, f32)>,
+    show_peak_hold: bool,
+    // The palette band each bar's halves were last colored with, and the
+    // levels to color them by this frame, held in those bands.
+    color_bands: Vec<(usize, usize)>,
+    color_levels: Vec<(f32, f32)>,
+    // Flash the display on beats, detected from jumps in buffer energy against
+    // the recent history. `flash` is how bright the flash still is, 0 to 1.
+    beat_flash: bool,
+    energy_history: VecDeque<f32>,
+    flash: f32,
+    // Normalized spectra for waterfall mode, newest first, at most `max_height` rows.
+    waterfall: VecDeque<Vec<f32>>,
+    // One analyzer per channel so stereo halves keep separate FFT history.
+    spectra: [Spectrum; 2],
+    // In split mode, the half not being worked on; while one is drawn, the
+    // rows it gets and the frame both halves go into.
+    other_view: View,
+    view_rows: Option<usize>,
+    composing: Option<Frame>,
+}
+
+impl Visualizer {
+    /// A visualizer sized to the terminal, or 64x21 when the [`Screen`] isn't one.
+    pub fn new(mode: VisualizerMode) -> Self {
+        Self::from_config(VisualizerConfig::default().mode(mode))
+    }
+
+    /// A visualizer with every setting taken from `config`. Whatever the config
+    /// leaves unsized follows the terminal, or 64x21 when the [`Screen`] isn't
+    /// one.
+    pub fn from_config(config: VisualizerConfig) -> Self {
+        let (num_bars, max_height) = terminal_layout().unwrap_or((DEFAULT_BARS, DEFAULT_HEIGHT));
+
+        let visualizer = Self {
+            mode: config.mode,
+            theme: Theme::default(),
+            color_depth: ColorDepth::Ansi256,
+            brightness: 1.0,
+            color: std::env::var_os("NO_COLOR").is_none() && Screen::current().is_terminal(),
+            num_bars,
+            max_height,
+            width: num_bars,
+            header: None,
+            progress: None,
+            readout: false,
+            freq_labels: false,
+            stats: None,
+            channel_peaks: Vec::new(),
+            clip_hold: 0.0,
+            last_frame: None,
+            spare_frame: None,
+            escapes: String::new(),
+            fed: Vec::new(),
+            feed_samples: FEED_SAMPLES,
+            png: None,
+            emit: None,
+            #[cfg(unix)]
+            socket: None,
+            graphics: None,
+            partial_blocks: true,
+            smooth_edges: false,
+            ascii: false,
+            braille: false,
+            centerline: Centerline::default(),
+            upward: false,
+            flip: false,
+            db_floor: None,
+            gamma: 1.0,
+            fixed_bars: None,
+            fixed_height: None,
+            too_small: false,
+            peak: INITIAL_PEAK,
+            gain: None,
+            normalization: Normalization::default(),
+            sample_rate: None,
+            resample: true,
+            loudness: None,
+            level_mode: LevelMode::default(),
+            gate: db_to_amplitude(-60.0),
+            decay: 0.92,
+            smoothing: 0.65,
+            smoothing_time: None,
+            envelope: EnvelopeMode::default(),
+            attack: Duration::from_millis(10),
+            release: Duration::from_millis(300),
+            columns: Vec::new(),
+            levels: Vec::new(),
+            bands: Vec::new(),
+            prev_columns: Vec::new(),
+            peak_hold: Vec::new(),
+            show_peak_hold: true,
+            color_bands: Vec::new(),
+            color_levels: Vec::new(),
+            beat_flash: false,
+            energy_history: VecDeque::new(),
+            flash: 0.0,
+            waterfall: VecDeque::new(),
+            spectra: [
+                Spectrum::new(WindowFunction::default()),
+                Spectrum::new(WindowFunction::default()),
+            ],
+            other_view: View::default(),
+            view_rows: None,
+            composing: None,
+        };
+
+        let mut visualizer = visualizer
+            .with_theme(config.theme)
+            .with_color_depth(config.color_depth)
+            .with_brightness(config.brightness)
+            .with_window(config.window)
+            .with_weighting(config.weighting)
+            .with_resample(config.resample)
+            .with_normalization(config.normalization)
+            .with_level_mode(config.level_mode)
+            .with_gate(config.gate)
+            .with_gamma(config.gamma)
+            .with_decay(config.decay)
+            .with_smoothing(config.smoothing)
+            .with_envelope(config.envelope)
+            .with_attack(config.attack)
+            .with_release(config.release)
+            .with_peak_hold(config.peak_hold)
+            .with_beat_flash(config.beat_flash)
+            .with_readout(config.readout)
+            .with_freq_labels(config.freq_labels)
+            .with_partial_blocks(config.partial_blocks)
+            .with_smooth_edges(config.smooth_edges)
+            .with_braille(config.braille)
+            .with_centerline(config.centerline)
+            .with_upward(config.upward)
+            .with_flip(config.flip)
+            .with_ascii(config.ascii);
+        if let Some(floor) = config.db_floor {
+            visualizer = visualizer.with_db(floor);
+        }
+        if let Some(bars) = config.bars {
+            visualizer = visualizer.with_bars(bars);
+        }
+        if let Some(gain) = config.gain {
+            visualizer = visualizer.with_gain(gain);
+        }
+        if let Some(time) = config.smoothing_time {
+            visualizer = visualizer.with_smoothing_time(time);
+        }
+        if let Some(rows) = config.height {
+            visualizer = visualizer.with_height(rows);
+        }
+        visualizer
+    }
+
+    /// Split each buffer into `num_bars` columns instead of following the terminal width.
+    pub fn with_bars(mut self, num_bars: usize) -> Self {
+        self.num_bars = num_bars.max(1);
+        self.fixed_bars = Some(self.num_bars);
+        self
+    }
+
+    /// Draw `rows` rows in all, header and progress line included, instead of
+    /// following the terminal height.
+    pub fn with_height(mut self, rows: usize) -> Self {
+        self.max_height = rows.max(1);
+        self.fixed_height = Some(self.max_height);
+        self
+    }
+
+    /// Taper FFT frames with `window` instead of the default Hann window.
+    pub fn with_window(mut self, window: WindowFunction) -> Self {
+        let weighting = self.spectra[0].weighting();
+        self.spectra = [Spectrum::new(window), Spectrum::new(window)];
+        self.with_weighting(weighting)
+    }
+
+    /// Weight spectrum magnitudes by `weighting` before grouping them into
+    /// bars, so the bands look about as loud as they sound. The curve follows
+    /// the rate the spectrum is analyzed at, the source's own with resampling
+    /// turned off.
+    pub fn with_weighting(mut self, weighting: Weighting) -> Self {
+        for spectrum in &mut self.spectra {
+            spectrum.set_weighting(weighting);
+        }
+        self
+    }
+
+    /// Color bars from the gradient of `theme` instead of the default, classic.
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Emit colors as `color_depth` escapes. The library defaults to
+    /// [`ColorDepth::Ansi256`], which nearly every terminal shows; the binary
+    /// starts from [`ColorDepth::detect`] instead.
+    pub fn with_color_depth(mut self, color_depth: ColorDepth) -> Self {
+        self.color_depth = color_depth;
+        self
+    }
+
+    /// Dim every color by `brightness`, from 0.0 (black) to 1.0 (the theme as
+    /// is, the default), for ambient displays where full brightness is harsh.
+    /// The 256-color palette gets the nearest dimmer entries.
+    pub fn with_brightness(mut self, brightness: f32) -> Self {
+        self.brightness = brightness.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Draw the bar and scope modes' centerline with another glyph, or hide it
+    /// and give the bars the full height.
+    pub fn with_centerline(mut self, centerline: Centerline) -> Self {
+        self.centerline = centerline;
+        self
+    }
+
+    /// Draw the bar modes as one bar per column, the larger of its two sides,
+    /// growing up from the bottom over every row instead of out from the
+    /// centerline. That doubles the height for levels whose sign means nothing,
+    /// like spectrum magnitudes.
+    pub fn with_upward(mut self, upward: bool) -> Self {
+        self.upward = upward;
+        self
+    }
+
+    /// Turn the bar and scope modes upside down: positive levels grow down
+    /// from the centerline, or with [`with_upward`](Self::with_upward) every
+    /// bar hangs from the top. Colors follow the levels as before.
+    pub fn with_flip(mut self, flip: bool) -> Self {
+        self.flip = flip;
+        self
+    }
+
+    /// Round bars to whole cells instead of drawing the tips with eighth blocks,
+    /// for fonts that render the partial blocks badly.
+    pub fn with_partial_blocks(mut self, partial_blocks: bool) -> Self {
+        self.partial_blocks = partial_blocks;
+        self
+    }
+
+    /// Anti-alias bar outlines with half blocks: tips are drawn to the nearest
+    /// half cell, and a bar beside one a whole cell or more taller gets a half
+    /// block shoulder, so neighbouring bars step down in halves. Takes the place
+    /// of eighth-block tips in bar mode.
+    pub fn with_smooth_edges(mut self, smooth_edges: bool) -> Self {
+        self.smooth_edges = smooth_edges;
+        self
+    }
+
+    /// Draw bars with `#` and the centerline with `-`, for terminals or fonts
+    /// without good Unicode coverage. Implies whole-cell bars.
+    pub fn with_ascii(mut self, ascii: bool) -> Self {
+        self.ascii = ascii;
+        self
+    }
+
+    /// Draw the bar and scope modes with Braille dots, two columns and four rows
+    /// of dots per cell. Ignored in ASCII mode, by the waterfall and by the VU
+    /// meter.
+    pub fn with_braille(mut self, braille: bool) -> Self {
+        self.braille = braille;
+        self
+    }
+
+    /// Scale bar heights in decibels, from `floor` dB (empty, e.g. -60.0) up to
+    /// 0 dB at the running peak, so quiet passages stay visible. The scope trace
+    /// stays linear.
+    pub fn with_db(mut self, floor: f32) -> Self {
+        self.db_floor = Some(floor.min(-1.0));
+        self
+    }
+
+    /// How slowly the auto-gain follows audio that gets quieter, from 0.0 (drop
+    /// straight to the new level) to 1.0 (never come down): the share of the
+    /// peak kept over each thirtieth of a second. Defaults to 0.92.
+    pub fn with_decay(mut self, decay: f32) -> Self {
+        self.decay = decay.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Brighten the colors and flash the centerline when a beat hits, judged
+    /// by sudden rises in energy over the last second or so. Off by default.
+    pub fn with_beat_flash(mut self, beat_flash: bool) -> Self {
+        self.beat_flash = beat_flash;
+        self
+    }
+
+    /// Raise bar heights (as fractions of full height) to this power: below 1.0
+    /// lifts quiet detail, above 1.0 leaves only the peaks standing. Defaults to
+    /// 1.0, no change.
+    pub fn with_gamma(mut self, gamma: f32) -> Self {
+        self.gamma = if gamma > 0.0 && gamma.is_finite() {
+            gamma
+        } else {
+            1.0
+        };
+        self
+    }
+
+    /// How much each bar follows the new frame rather than the last one, from
+    /// 1.0 (no smoothing, instant response) down toward 0.0 (calm but laggy).
+    /// Defaults to 0.65.
+    pub fn with_smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Smooth bars by a time constant instead, the time to get about two
+    /// thirds of the way to a new level. The weight for each frame is worked
+    /// out from how much audio it covers, so the easing looks the same at any
+    /// frame rate or buffer size.
+    pub fn with_smoothing_time(mut self, time: Duration) -> Self {
+        self.smoothing_time = Some(time);
+        self
+    }
+
+    /// Ease levels with `mode`. [`EnvelopeMode::AttackRelease`] goes by
+    /// [`with_attack`](Self::with_attack) and
+    /// [`with_release`](Self::with_release) in place of the smoothing.
+    pub fn with_envelope(mut self, mode: EnvelopeMode) -> Self {
+        self.envelope = mode;
+        self
+    }
+
+    /// How quickly rising levels catch up in attack/release mode: the time to
+    /// get about two thirds of the way to a new level. Defaults to 10 ms.
+    pub fn with_attack(mut self, attack: Duration) -> Self {
+        self.attack = attack;
+        self
+    }
+
+    /// Likewise for falling levels. Defaults to 300 ms.
+    pub fn with_release(mut self, release: Duration) -> Self {
+        self.release = release;
+        self
+    }
+
+    /// Mark each bar's recent peak with a marker that slowly falls back, like a
+    /// hardware meter. On by default for the bar modes.
+    pub fn with_peak_hold(mut self, show: bool) -> Self {
+        self.show_peak_hold = show;
+        self
+    }
+
+    /// Draw levels quieter than `dbfs` as silence, so the auto-gain doesn't blow
+    /// up the noise floor. Defaults to -60 dBFS.
+    pub fn with_gate(mut self, dbfs: f32) -> Self {
+        self.gate = db_to_amplitude(dbfs);
+        self
+    }
+
+    /// Write frames as numbered PNG images in `dir` instead of to the terminal,
+    /// 30 per second of the playing time given to [`Visualizer::set_progress`]
+    /// (or one per frame without it). The layout stays at the size of the
+    /// visualizer rather than following the terminal, and colors are always on.
+    pub fn with_png_output(mut self, dir: PathBuf) -> Self {
+        self.png = Some(PngOutput::new(dir));
+        self.color = true;
+        self
+    }
+
+    /// Show a line under the bars with the frame rate over the last second and
+    /// how long frames have lately spent decoding, analyzing and drawing.
+    pub fn with_stats(mut self, stats: bool) -> Self {
+        self.stats = stats.then(FrameStats::default);
+        self
+    }
+
+    // Count `time` spent decoding toward the next frame's statistics.
+    pub(crate) fn add_decode_time(&mut self, time: Duration) {
+        if let Some(stats) = &mut self.stats {
+            stats.add_decode(time);
+        }
+    }
+
+    /// Write each frame's bar levels to the output as `format` text instead of
+    /// drawing them, with the playing time given to [`Visualizer::set_progress`].
+    /// Like PNG output, the layout stays put rather than following the terminal.
+    pub fn with_emit(mut self, format: EmitFormat) -> Self {
+        self.emit = Some(LevelWriter::new(format));
+        self
+    }
+
+    /// Also send each frame's bar levels, as the JSON lines of
+    /// [`EmitFormat::Json`], to the Unix domain socket or named pipe at
+    /// `path`. A consumer can come and go: frames sent while nobody is
+    /// listening, or while it's falling behind, are dropped rather than
+    /// waited for.
+    #[cfg(unix)]
+    pub fn with_level_socket(mut self, path: PathBuf) -> Self {
+        self.socket = Some(LevelSocket::new(path));
+        self
+    }
+
+    /// Draw the visualization as a Sixel image, for terminals that show them
+    /// (see [`sixel_supported`](crate::sixel_supported)). The header and
+    /// progress lines stay text.
+    pub fn with_sixel(mut self, sixel: bool) -> Self {
+        self.graphics = sixel.then_some(Graphics::Sixel);
+        self
+    }
+
+    /// Draw the visualization as an image with the Kitty graphics protocol, for
+    /// Kitty, Ghostty and other terminals that speak it (see
+    /// [`kitty_supported`](crate::kitty_supported)). The header and progress
+    /// lines stay text.
+    pub fn with_kitty(mut self, kitty: bool) -> Self {
+        self.graphics = kitty.then_some(Graphics::Kitty);
+        self
+    }
+
+    /// Multiply levels by a fixed `gain` instead of scaling them to the running
+    /// peak or loudness, so tracks can be compared by level. A full-scale sample
+    /// fills `gain` times the height, and anything past the top is clipped.
+    pub fn with_gain(mut self, gain: f32) -> Self {
+        if gain > 0.0 && gain.is_finite() {
+            self.gain = Some(gain);
+        }
+        self
+    }
+
+    /// Scale bars against recent loudness rather than the sample peak. Loudness
+    /// can only be measured once [`Visualizer::set_sample_rate`] has been called.
+    pub fn with_normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Measure each waveform bar's chunk of samples by `mode` instead of the
+    /// default peak and average blend.
+    pub fn with_level_mode(mut self, mode: LevelMode) -> Self {
+        self.level_mode = mode;
+        self
+    }
+
+    /// Show each channel's sample peak in dBFS, like `L: -6.2  R: -5.8`, on a
+    /// line under the visualization.
+    pub fn with_readout(mut self, readout: bool) -> Self {
+        self.readout = readout;
+        self
+    }
+
+    /// Mark where 60 Hz, 250 Hz, 1 kHz, 4 kHz and 16 kHz fall on a line under
+    /// the bars in spectrum mode, leaving out labels there's no room for.
+    pub fn with_freq_labels(mut self, freq_labels: bool) -> Self {
+        self.freq_labels = freq_labels;
+        self
+    }
+
+    /// Resample audio to 44.1 kHz before the spectrum modes analyze it, so
+    /// each bar covers the same frequencies whatever the file's rate. On by
+    /// default; it takes effect once [`Visualizer::set_sample_rate`] is called.
+    pub fn with_resample(mut self, resample: bool) -> Self {
+        self.resample = resample;
+        self
+    }
+
+    /// Draw a frame from [`feed`](Self::feed) once it has gathered this many
+    /// samples. The default, 2048, is one spectrum window; fewer give more
+    /// frames a second but coarser spectra.
+    pub fn with_feed_samples(mut self, samples: usize) -> Self {
+        self.feed_samples = samples.max(1);
+        self
+    }
+
+    /// Tell the visualizer the sample rate of the buffers it's given.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = Some(sample_rate);
+    }
+
+    pub fn mode(&self) -> VisualizerMode {
+        self.mode
+    }
+
+    /// Switch to `mode`, starting it afresh as [`reset`](Self::reset) does.
+    pub fn set_mode(&mut self, mode: VisualizerMode) {
+        if mode != self.mode {
+            self.reset();
+        }
+        self.mode = mode;
+    }
+
+    /// Show `header` on its own line above the bars, or nothing for `None`.
+    pub fn set_header(&mut self, header: Option<String>) {
+        self.header = header;
+    }
+
+    /// Show `elapsed / total` and a progress bar on a line below the bars. With
+    /// no `total`, as for a stream, only the elapsed time is shown.
+    pub fn set_progress(&mut self, elapsed: Duration, total: Option<Duration>) {
+        self.progress = Some((elapsed, total));
+    }
+
+    /// Forget everything carried over from earlier audio, as at the start of a
+    /// new track: smoothed levels, peak holds, the running peak behind the
+    /// auto-gain and the waterfall's history.
+    pub fn reset(&mut self) {
+        self.clear_levels();
+        self.fed.clear();
+        self.peak = INITIAL_PEAK;
+        self.other_view = View::default();
+        self.waterfall.clear();
+    }
+
+    // Forget per-bar motion so the next frame doesn't glide in from old levels.
+    pub(crate) fn clear_levels(&mut self) {
+        self.prev_columns.clear();
+        self.peak_hold.clear();
+        self.color_bands.clear();
+        let view = &mut self.other_view;
+        view.prev_columns.clear();
+        view.peak_hold.clear();
+        view.color_bands.clear();
+        self.energy_history.clear();
+        self.flash = 0.0;
+        self.clip_hold = 0.0;
+    }
+
+    // Pick up a terminal resize since the last frame. A fixed size is cut down
+    // to fit, and back up once the terminal grows again.
+    fn fit_to_terminal(&mut self) {
+        if self.png.is_some() || self.emit.is_some() {
+            return;
+        }
+        if let Some((cols, rows)) = terminal_layout() {
+            let rows = self.fixed_height.map_or(rows, |height| height.min(rows));
+            if (cols, rows) != (self.width, self.max_height) {
+                // The terminal may have reflowed what was on screen.
+                self.last_frame = None;
+            }
+            self.num_bars = self.fixed_bars.map_or(cols, |bars| bars.min(cols));
+            self.max_height = rows;
+            self.width = cols;
+            self.too_small = cols < MIN_COLS || rows < self.reserved_rows() + MIN_PLOT_ROWS;
+        }
+    }
+
+    // The rate to tell the spectrum it's getting, when it should resample.
+    fn analysis_rate(&self) -> Option<u32> {
+        self.sample_rate.filter(|_| self.resample)
+    }
+
+    // The rate the spectrum's bins end up at.
+    fn spectrum_rate(&self) -> u32 {
+        match self.sample_rate {
+            Some(rate) if !self.resample => rate,
+            _ => ANALYSIS_RATE,
+        }
+    }
+
+    // Whether this frame goes on a Braille canvas.
+    fn braille_active(&self) -> bool {
+        self.braille
+            && !self.ascii
+            && !matches!(self.mode, VisualizerMode::Waterfall | VisualizerMode::Vu)
+    }
+
+    // Columns to compute per frame: one per cell, or one per dot column on a
+    // Braille canvas.
+    fn column_count(&self) -> usize {
+        if self.braille_active() {
+            self.num_bars * 2
+        } else {
+            self.num_bars
+        }
+    }
+
+    // Append raw levels for `num_bars` bars from one channel to `columns`, using
+    // the active mode.
+    fn columns(
+        &mut self,
+        channel: usize,
+        samples: &[f32],
+        num_bars: usize,
+        columns: &mut Vec<(f32, f32)>,
+    ) {
+        match self.mode {
+            VisualizerMode::Waveform => {
+                waveform_columns(samples, num_bars, self.level_mode, columns)
+            }
+            VisualizerMode::Scope => scope_columns(samples, num_bars, columns),
+            VisualizerMode::Spectrum | VisualizerMode::Waterfall | VisualizerMode::Radial => {
+                let rate = self.analysis_rate();
+                self.spectra[channel].set_rate(self.spectrum_rate());
+                self.spectra[channel].columns(samples, rate, num_bars, columns)
+            }
+            VisualizerMode::Mirror => {
+                let mut bands = std::mem::take(&mut self.bands);
+                bands.clear();
+                let rate = self.analysis_rate();
+                self.spectra[channel].set_rate(self.spectrum_rate());
+                self.spectra[channel].columns(samples, rate, num_bars.div_ceil(2), &mut bands);
+                mirror(&bands, num_bars, columns);
+                self.bands = bands;
+            }
+            // One meter per channel, however wide the display.
+            VisualizerMode::Vu => {
+                let level = rms(samples);
+                columns.push((level, level));
+            }
+            // Each half is analyzed in its own mode instead.
+            VisualizerMode::Split => {}
+        }
+    }
+
+    /// The levels for one mono buffer, without drawing anything: a `(positive,
+    /// negative)` pair per bar (per dot column with Braille), each from 0.0 to
+    /// 1.0 of the height on its side of the centerline. The bar modes gate,
+    /// normalize and smooth them exactly as drawn. Scope mode gives the sample
+    /// under each column split by sign, waterfall mode the unsmoothed band
+    /// magnitude on both sides, VU mode a single pair holding the RMS level
+    /// against full scale, and split mode the spectrum half's. An empty buffer
+    /// leaves the last levels in place.
+    pub fn analyze(&mut self, samples: &[f32]) -> &[(f32, f32)] {
+        if !samples.is_empty() {
+            self.analyze_channels(&[samples]);
+        }
+        &self.levels
+    }
+
+    /// Draw one frame for a mono buffer into `out`. Nothing is written for an
+    /// empty buffer. After the first frame only cells that changed are sent, so
+    /// `out` should be the same screen every time.
+    pub fn render<W: Write>(&mut self, out: &mut W, samples: &[f32]) -> io::Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        self.render_channels(out, &[samples])
+    }
+
+    /// Draw one frame with the left channel on the left half of the bars and
+    /// the right channel on the right half.
+    pub fn render_stereo<W: Write>(
+        &mut self,
+        out: &mut W,
+        left: &[f32],
+        right: &[f32],
+    ) -> io::Result<()> {
+        if left.is_empty() && right.is_empty() {
+            return Ok(());
+        }
+
+        self.render_channels(out, &[left, right])
+    }
+
+    fn render_channels<W: Write>(&mut self, out: &mut W, channels: &[&[f32]]) -> io::Result<()> {
+        self.fit_to_terminal();
+        let started = Instant::now();
+        self.analyze_channels(channels);
+        let analyzed = Instant::now();
+        let result = self.draw(out);
+        if let Some(stats) = &mut self.stats {
+            stats.frame(analyzed - started, analyzed.elapsed());
+        }
+        result
+    }
+
+    /// Hand over mono samples in chunks of any size, drawing a frame once enough
+    /// have gathered for one (see [`with_feed_samples`](Self::with_feed_samples)).
+    /// Returns the frame's text, or `None` while still gathering. A chunk
+    /// holding several frames' worth draws only the latest of them, and
+    /// anything beyond it waits for the next call. As with
+    /// [`render`](Self::render), only changed cells are sent after the first
+    /// frame, so each one should be written to the same screen.
+    pub fn feed(&mut self, samples: &[f32]) -> Option<String> {
+        self.fed.extend_from_slice(samples);
+        let frames = self.fed.len() / self.feed_samples;
+        if frames == 0 {
+            return None;
+        }
+
+        let end = frames * self.feed_samples;
+        let start = end - self.feed_samples;
+        let mut fed = std::mem::take(&mut self.fed);
+        let mut out = Vec::new();
+        let drawn = self.render(&mut out, &fed[start..end]);
+        fed.drain(..end);
+        self.fed = fed;
+        // Writing to a Vec can't fail, and frames are built from strings.
+        drawn.ok()?;
+        String::from_utf8(out).ok()
+    }
+
+    // Update `levels` from one channel spread over every bar, or two sharing
+    // them half and half.
+    fn analyze_channels(&mut self, channels: &[&[f32]]) {
+        self.measure(channels);
+        self.channel_peaks.clear();
+        self.channel_peaks.extend(channels.iter().map(|channel| {
+            channel
+                .iter()
+                .fold(0.0f32, |peak, &sample| peak.max(sample.abs()))
+        }));
+        let seconds = channels.first().map_or(0, |channel| channel.len()) as f32
+            / self.sample_rate.unwrap_or(44_100) as f32;
+        self.clip_hold = if self.channel_peaks.iter().any(|&peak| peak >= 1.0) {
+            CLIP_HOLD
+        } else {
+            (self.clip_hold - seconds).max(0.0)
+        };
+        self.detect_beat(channels);
+
+        if self.mode == VisualizerMode::Split {
+            // Analyzed as the spectrum, which gives the levels, and again as
+            // the waveform underneath.
+            self.in_view(SPLIT_UPPER, |this| this.analyze_view(channels, seconds));
+            self.in_view(SPLIT_LOWER, |this| this.analyze_view(channels, seconds));
+        } else {
+            self.analyze_view(channels, seconds);
+        }
+    }
+
+    // Update `levels` for the active mode from the `seconds` of audio in
+    // `channels`.
+    fn analyze_view(&mut self, channels: &[&[f32]], seconds: f32) {
+        let count = self.column_count();
+        let mut columns = std::mem::take(&mut self.columns);
+        columns.clear();
+        match channels {
+            [mono] => self.columns(0, mono, count, &mut columns),
+            [left, right, ..] => {
+                let left_bars = count / 2;
+                self.columns(0, left, left_bars, &mut columns);
+                self.columns(1, right, count - left_bars, &mut columns);
+            }
+            [] => {}
+        }
+        self.normalize(&mut columns, seconds);
+        self.columns = columns;
+    }
+
+    // Turn raw `columns`, from `seconds` of audio, into `levels` for the
+    // active mode.
+    fn normalize(&mut self, columns: &mut [(f32, f32)], seconds: f32) {
+        let mut levels = std::mem::take(&mut self.levels);
+        levels.clear();
+
+        if self.mode == VisualizerMode::Scope {
+            let peak = self.reference(columns, seconds);
+            levels.extend(columns.iter().map(|&(pos, neg)| (pos / peak, neg / peak)));
+            self.levels = levels;
+            return;
+        }
+
+        // Below the gate it's noise floor; zero it so quiet passages stay still.
+        for (pos, neg) in columns.iter_mut() {
+            if *pos < self.gate {
+                *pos = 0.0;
+            }
+            if *neg < self.gate {
+                *neg = 0.0;
+            }
+        }
+        // The VU meter reads against full scale rather than the running peak.
+        let peak = if self.mode == VisualizerMode::Vu {
+            1.0
+        } else {
+            self.reference(columns, seconds)
+        };
+
+        if self.mode == VisualizerMode::Waterfall {
+            levels.extend(columns.iter().map(|&(magnitude, _)| {
+                let level = self.scale(magnitude / peak);
+                (level, level)
+            }));
+            self.levels = levels;
+            return;
+        }
+
+        // How far each bar moves toward its new level, rising and falling. A
+        // time constant covers 1 - 1/e of the way in that long.
+        let follow = |time: Duration| {
+            if time.is_zero() {
+                1.0
+            } else {
+                1.0 - (-seconds / time.as_secs_f32()).exp()
+            }
+        };
+        let (rise, fall) = match (self.envelope, self.smoothing_time) {
+            (EnvelopeMode::Linear, None) => (self.smoothing, self.smoothing),
+            (EnvelopeMode::Linear, Some(time)) => (follow(time), follow(time)),
+            (EnvelopeMode::AttackRelease, _) => (follow(self.attack), follow(self.release)),
+        };
+
+        // Keep the levels of surviving bars across a resize; new ones start flat.
+        self.prev_columns.resize(columns.len(), (0.0, 0.0));
+        levels.extend(columns.iter().zip(self.prev_columns.iter()).map(
+            |(&(pos, neg), &(prev_pos, prev_neg))| {
+                let norm_pos = self.scale(pos / peak);
+                let norm_neg = self.scale(neg / peak);
+                (
+                    ease(norm_pos, prev_pos, rise, fall),
+                    ease(norm_neg, prev_neg, rise, fall),
+                )
+            },
+        ));
+        self.prev_columns.copy_from_slice(&levels);
+
+        // Holds jump to a new high and otherwise fall a fixed amount per
+        // reference frame's worth of audio.
+        const HOLD_FALL: f32 = 0.015;
+        let fall = HOLD_FALL * seconds * REFERENCE_FPS;
+        self.peak_hold.resize(levels.len(), (0.0, 0.0));
+        for (hold, &(pos, neg)) in self.peak_hold.iter_mut().zip(&levels) {
+            hold.0 = pos.max(hold.0 - fall);
+            hold.1 = neg.max(hold.1 - fall);
+        }
+        self.levels = levels;
+    }
+
+    // Rows left for the visualization between the header and the lines under it.
+    fn plot_rows(&self) -> usize {
+        if let Some(rows) = self.view_rows {
+            return rows;
+        }
+        self.max_height.saturating_sub(self.reserved_rows()).max(1)
+    }
+
+    // Rows taken by the header, frequency labels, readout, statistics and
+    // progress lines.
+    fn reserved_rows(&self) -> usize {
+        usize::from(self.header.is_some())
+            + usize::from(self.freq_labels_active())
+            + usize::from(self.readout)
+            + usize::from(self.stats.is_some())
+            + usize::from(self.progress.is_some())
+    }
+
+    // An empty frame, reusing the spare one's buffers when there is one.
+    fn blank_frame(&mut self) -> Frame {
+        match self.spare_frame.take() {
+            Some(mut frame) => {
+                frame.clear();
+                frame
+            }
+            None => Frame::new(self.color_depth),
+        }
+    }
+
+    // A new frame with the header drawn, ready for the visualization rows. For
+    // a second after a sample reaches full scale, `CLIP` shows at its right.
+    fn frame_start(&mut self) -> Frame {
+        // Half of split mode goes on under the header and the half above it.
+        if let Some(frame) = self.composing.take() {
+            return frame;
+        }
+        let mut frame = self.blank_frame();
+        if let Some(header) = &self.header {
+            let style = Style {
+                bold: self.color,
+                ..Style::default()
+            };
+            const CLIP: &str = " CLIP";
+            let clipping = self.clip_hold > 0.0 && self.width > CLIP.len();
+            let width = if clipping {
+                self.width - CLIP.len()
+            } else {
+                self.width
+            };
+            let end = header.char_indices().nth(width);
+            let header = &header[..end.map_or(header.len(), |(i, _)| i)];
+            frame.push_styled_str(style, header);
+            if clipping {
+                frame.push_str(&" ".repeat(width - header.chars().count() + 1));
+                let style = Style {
+                    fg: self.color.then(|| self.dim((230, 40, 40))),
+                    bold: true,
+                    inverse: !self.color,
+                };
+                frame.push_styled_str(style, CLIP.trim_start());
+            }
+            frame.end_row();
+        }
+        frame
+    }
+
+    // Whether this frame has a frequency axis under it.
+    fn freq_labels_active(&self) -> bool {
+        self.freq_labels && self.mode == VisualizerMode::Spectrum
+    }
+
+    // Round frequencies under the cells of the bars that hold them, for each
+    // channel's half in stereo, shifted in from the right edge. Going left to
+    // right, a label that would run into the one before it is left out.
+    fn freq_label_line(&self) -> String {
+        const LABELS: [(f32, &str); 5] = [
+            (60.0, "60"),
+            (250.0, "250"),
+            (1_000.0, "1k"),
+            (4_000.0, "4k"),
+            (16_000.0, "16k"),
+        ];
+
+        let rate = self.spectrum_rate();
+        let count = self.column_count();
+        let halves = match self.channel_peaks.len() {
+            2 => [(0, count / 2), (count / 2, count - count / 2)],
+            _ => [(0, count), (count, 0)],
+        };
+        let per_cell = if self.braille_active() { 2 } else { 1 };
+
+        let mut line = vec![' '; self.num_bars.min(self.width.max(1))];
+        // The first cell a label may start in, a space after the last one.
+        let mut free = 0;
+        for (start, bars) in halves {
+            for (frequency, label) in LABELS {
+                let Some(bar) = bar_for_frequency(frequency, rate, bars) else {
+                    continue;
+                };
+                let first = ((start + bar) / per_cell)
+                    .saturating_sub(label.len() / 2)
+                    .min(line.len().saturating_sub(label.len()));
+                let end = first + label.len();
+                if first < free || end > line.len() {
+                    continue;
+                }
+                for (cell, glyph) in line[first..end].iter_mut().zip(label.chars()) {
+                    *cell = glyph;
+                }
+                free = end + 1;
+            }
+        }
+        line.into_iter().collect()
+    }
+
+    // Add the frequency labels, readout, statistics and progress lines under
+    // the visualization rows.
+    fn frame_end(&self, frame: &mut Frame) {
+        if self.view_rows.is_some() {
+            return;
+        }
+        if self.freq_labels_active() {
+            frame.push_str(&self.freq_label_line());
+            frame.end_row();
+        }
+        if self.readout {
+            let labels: &[&str] = match self.channel_peaks.len() {
+                2 => &["L", "R"],
+                _ => &["Peak"],
+            };
+            let line = labels
+                .iter()
+                .zip(&self.channel_peaks)
+                .map(|(label, &peak)| format!("{}: {}", label, format_dbfs(peak, self.ascii)))
+                .collect::<Vec<_>>()
+                .join("  ");
+            let line: String = line.chars().take(self.width).collect();
+            frame.push_str(&line);
+            frame.end_row();
+        }
+        if let Some(stats) = &self.stats {
+            let line: String = stats.line().chars().take(self.width).collect();
+            frame.push_str(&line);
+            frame.end_row();
+        }
+
+        let Some((elapsed, total)) = self.progress else {
+            return;
+        };
+
+        let mut line = format_time(elapsed);
+        if let Some(total) = total {
+            line.push_str(" / ");
+            line.push_str(&format_time(total));
+            line.push(' ');
+
+            let (done, todo) = if self.ascii {
+                ('=', '-')
+            } else {
+                ('━', '─')
+            };
+            let track = self.width.saturating_sub(line.chars().count());
+            let fraction = if total.is_zero() {
+                1.0
+            } else {
+                (elapsed.as_secs_f64() / total.as_secs_f64()).min(1.0)
+            };
+            let filled = (fraction * track as f64).round() as usize;
+            line.extend(std::iter::repeat_n(done, filled));
+            line.extend(std::iter::repeat_n(todo, track - filled));
+        }
+
+        let line: String = line.chars().take(self.width).collect();
+        frame.push_str(&line);
+        frame.end_row();
+    }
+
+    // Write `frame` to `out`, redrawing only what changed since the last one, or
+    // out as images.
+    fn present<W: Write>(&mut self, out: &mut W, mut frame: Frame) -> io::Result<()> {
+        if self.view_rows.is_some() {
+            self.composing = Some(frame);
+            return Ok(());
+        }
+        if let Some(png) = &mut self.png {
+            let result = png.write(&frame, self.progress.map(|(elapsed, _)| elapsed));
+            self.spare_frame = Some(frame);
+            return result;
+        }
+
+        // The visualization rows go out as an image over blank text rows, which
+        // then never change, so the diff leaves the image alone.
+        let image = self.graphics.filter(|_| !self.too_small).map(|graphics| {
+            const DEFAULT_CELL: (usize, usize) = (8, 16);
+            let first = usize::from(self.header.is_some());
+            let rows = first..first + self.plot_rows();
+            let image = rasterize(&frame, rows.clone(), cell_pixels().unwrap_or(DEFAULT_CELL));
+            frame.blank_rows(rows.clone());
+            (graphics, rows, image)
+        });
+
+        self.escapes.clear();
+        match &self.last_frame {
+            Some(last) => frame.write_diff(last, &mut self.escapes),
+            None => frame.write_full(&mut self.escapes),
+        }
+        if let Some((graphics, rows, image)) = image {
+            self.escapes
+                .push_str(&format!("\x1B[{};1H", rows.start + 1));
+            match graphics {
+                Graphics::Sixel => {
+                    let (width, height) = image.dimensions();
+                    let (width, height) = (width as usize, height as usize);
+                    sixel::encode(width, height, image.as_raw(), &mut self.escapes)
+                }
+                Graphics::Kitty => {
+                    let cells = (self.num_bars, rows.len());
+                    kitty::encode(&image, cells, &mut self.escapes)
+                }
+            }
+        }
+        out.write_all(self.escapes.as_bytes())?;
+        out.flush()?;
+        self.spare_frame = self.last_frame.replace(frame);
+        Ok(())
+    }
+
+    // Fade the flash, and set it off again when this buffer's energy jumps well
+    // above the average of the last HISTORY buffers.
+    fn detect_beat(&mut self, channels: &[&[f32]]) {
+        // About a second of typical decoder buffers.
+        const HISTORY: usize = 43;
+        const THRESHOLD: f32 = 1.5;
+        const FADE: f32 = 0.7;
+
+        if !self.beat_flash {
+            return;
+        }
+        self.flash = if self.flash > 0.05 {
+            self.flash * FADE
+        } else {
+            0.0
+        };
+
+        let samples = channels.iter().flat_map(|channel| channel.iter());
+        let (sum, count) = samples.fold((0.0f32, 0usize), |(sum, count), &sample| {
+            (sum + sample * sample, count + 1)
+        });
+        let energy = sum / count.max(1) as f32;
+        let average = self.energy_history.iter().sum::<f32>() / HISTORY as f32;
+
+        // Wait for a full history, and for the last flash to mostly fade.
+        let onset = self.energy_history.len() == HISTORY
+            && energy > average * THRESHOLD
+            && energy.sqrt() > self.gate
+            && self.flash < 0.25;
+        if onset {
+            self.flash = 1.0;
+        }
+
+        self.energy_history.push_back(energy);
+        if self.energy_history.len() > HISTORY {
+            self.energy_history.pop_front();
+        }
+    }
+
+    // Feed the loudness meter when normalizing by loudness.
+    fn measure(&mut self, channels: &[&[f32]]) {
+        let Some(sample_rate) = self.sample_rate else {
+            return;
+        };
+        if self.normalization != Normalization::Loudness {
+            return;
+        }
+        let meter = match self.loudness.take() {
+            Some(meter) if meter.fits(sample_rate, channels.len()) => meter,
+            _ => LoudnessMeter::new(sample_rate, channels.len()),
+        };
+        self.loudness.insert(meter).push(channels);
+    }
+
+    // The level that maps to full height: the running peak, a fixed headroom
+    // above the measured loudness once there is a measurement, or whatever the
+    // fixed gain makes of it.
+    fn reference(&mut self, columns: &[(f32, f32)], seconds: f32) -> f32 {
+        // Roughly the crest factor of typical music, in amplitude.
+        const LOUDNESS_HEADROOM: f32 = 3.0;
+
+        if let Some(gain) = self.gain {
+            return 1.0 / gain;
+        }
+
+        let peak = self.track_peak(columns, seconds);
+        let lufs = self.loudness.as_ref().and_then(LoudnessMeter::loudness);
+        match lufs {
+            Some(lufs) if self.normalization == Normalization::Loudness => {
+                let rms = 10f64.powf((lufs + 0.691) / 20.0) as f32;
+                (rms * LOUDNESS_HEADROOM).max(1e-3)
+            }
+            _ => peak,
+        }
+    }
+
+    // Follow the loudest column of `seconds` of audio: jump up to a new peak,
+    // decay slowly otherwise. Returns the level that maps to full height.
+    fn track_peak(&mut self, columns: &[(f32, f32)], seconds: f32) -> f32 {
+        let frame_peak = columns
+            .iter()
+            .fold(0.0f32, |acc, &(pos, neg)| acc.max(pos.max(neg)));
+
+        if frame_peak > self.peak {
+            self.peak = frame_peak;
+        } else {
+            let decay = self.decay.powf(seconds * REFERENCE_FPS);
+            self.peak = self.peak * decay + frame_peak * (1.0 - decay);
+        }
+
+        self.peak.max(1e-3)
+    }
+
+    // Height fraction for a level already normalized against the peak.
+    fn scale(&self, level: f32) -> f32 {
+        let level = level.clamp(0.0, 1.0);
+        // The VU meter is always in decibels, like the hardware it mimics.
+        let floor = match self.mode {
+            VisualizerMode::Vu => self.db_floor.or(Some(VU_FLOOR_DB)),
+            _ => self.db_floor,
+        };
+        let height = match floor {
+            Some(floor) if level > 0.0 => {
+                let db = (20.0 * level.log10()).max(floor);
+                1.0 - db / floor
+            }
+            Some(_) => 0.0,
+            None => level,
+        };
+        height.powf(self.gamma)
+    }
+
+    // Draw the current levels in the active mode.
+    fn draw<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let elapsed = self.progress.map(|(elapsed, _)| elapsed);
+        #[cfg(unix)]
+        if let Some(socket) = &mut self.socket {
+            socket.send(elapsed, &self.levels);
+        }
+        if let Some(emit) = &mut self.emit {
+            return emit.write(out, elapsed, &self.levels);
+        }
+
+        if self.too_small {
+            return self.draw_too_small(out);
+        }
+
+        if self.mode == VisualizerMode::Split {
+            return self.draw_split(out);
+        }
+        self.draw_levels(out)
+    }
+
+    fn draw_levels<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let levels = std::mem::take(&mut self.levels);
+        let result = match self.mode {
+            VisualizerMode::Scope => self.draw_scope(out, &levels),
+            VisualizerMode::Waterfall => self.draw_waterfall(out, &levels),
+            VisualizerMode::Radial => self.draw_radial(out, &levels),
+            VisualizerMode::Vu => self.draw_vu(out, &levels),
+            _ if self.braille_active() => self.draw_braille_bars(out, &levels),
+            _ => self.draw_bars(out, &levels),
+        };
+        self.levels = levels;
+        result
+    }
+
+    // Both halves of split mode in one frame, the spectrum getting the odd row.
+    // Each is drawn as its own mode would be, into the rows under the last.
+    fn draw_split<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let rows = self.plot_rows();
+        let frame = self.frame_start();
+        self.composing = Some(frame);
+        let halves = [(SPLIT_UPPER, rows - rows / 2), (SPLIT_LOWER, rows / 2)];
+        let mut result = Ok(());
+        for (mode, rows) in halves {
+            self.view_rows = Some(rows);
+            result = result.and_then(|_| self.in_view(mode, |this| this.draw_levels(out)));
+        }
+        self.view_rows = None;
+
+        let mut frame = self.composing.take().unwrap_or_else(|| self.blank_frame());
+        result?;
+        self.frame_end(&mut frame);
+        self.present(out, frame)
+    }
+
+    // Run `f` as the `mode` half of split mode, with that half's state in place.
+    fn in_view<T>(&mut self, mode: VisualizerMode, f: impl FnOnce(&mut Self) -> T) -> T {
+        let swap = mode == SPLIT_LOWER;
+        if swap {
+            self.swap_view();
+        }
+        self.mode = mode;
+        let result = f(self);
+        self.mode = VisualizerMode::Split;
+        if swap {
+            self.swap_view();
+        }
+        result
+    }
+
+    fn swap_view(&mut self) {
+        let view = &mut self.other_view;
+        std::mem::swap(&mut self.levels, &mut view.levels);
+        std::mem::swap(&mut self.prev_columns, &mut view.prev_columns);
+        std::mem::swap(&mut self.peak_hold, &mut view.peak_hold);
+        std::mem::swap(&mut self.color_bands, &mut view.color_bands);
+        std::mem::swap(&mut self.color_levels, &mut view.color_levels);
+        std::mem::swap(&mut self.peak, &mut view.peak);
+    }
+
+    // A notice in place of the frame until the terminal is big enough again.
+    // Redrawing it only writes what changed, which is nothing until a resize.
+    fn draw_too_small<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let mut frame = self.blank_frame();
+        let notice = format!(
+            "terminal too small: need {}x{}",
+            MIN_COLS,
+            self.reserved_rows() + MIN_PLOT_ROWS + 1
+        );
+        let notice: String = notice.chars().take(self.width.max(1)).collect();
+        frame.push_str(&notice);
+        frame.end_row();
+        self.present(out, frame)
+    }
+
+    fn draw_bars<W: Write>(&mut self, out: &mut W, smoothed: &[(f32, f32)]) -> io::Result<()> {
+        let mut frame = self.frame_start();
+        let total_rows = self.plot_rows();
+        // Drawing upward, every row goes to the upper half.
+        let full_height = self.full_height();
+        let (top_rows, bottom_rows) = if full_height {
+            (total_rows, 0)
+        } else {
+            split_rows(total_rows)
+        };
+        let mid_row = top_rows;
+
+        // Bar heights in steps: eighths of a row so the tip can be a partial block,
+        // halves with smooth edges, or whole rows when partial blocks are off.
+        let steps = if self.ascii {
+            1
+        } else if self.smooth_edges {
+            2
+        } else if self.partial_blocks {
+            8
+        } else {
+            1
+        };
+        let centerline = self.centerline_glyph();
+        let colors = self.hold_color_bands(smoothed);
+        // Each half scales to its own rows, so full scale reaches the edge on both.
+        let to_steps = |level: f32, rows: usize| (level * (rows * steps) as f32).round() as usize;
+
+        // Upside down, the upper half's bars hang down and the lower half's
+        // stand up, with their tips and markers turned to match.
+        let flipped = self.flipped();
+        let (hold_up, hold_down) = match (self.ascii, flipped) {
+            (true, _) => ('=', '='),
+            (false, false) => ('▔', '▁'),
+            (false, true) => ('▁', '▔'),
+        };
+        // The cell holding a bar's peak marker, if it sits clear of the bar itself.
+        let hold_depth = |height: usize, hold: f32, rows: usize| {
+            let depth = ((hold * rows as f32) as usize).min(rows.saturating_sub(1));
+            let bar_cells = height.div_ceil(steps);
+            (self.show_peak_hold && hold > 0.0 && depth >= bar_cells).then_some(depth)
+        };
+        // Each bar's height in steps above and below the centerline.
+        let (mut up, mut down): (Vec<usize>, Vec<usize>) = smoothed
+            .iter()
+            .map(|&(pos, neg)| {
+                if full_height {
+                    (to_steps(pos.max(neg), top_rows), 0)
+                } else {
+                    (to_steps(pos, top_rows), to_steps(neg, bottom_rows))
+                }
+            })
+            .unzip();
+        if self.smooth_edges && steps == 2 {
+            add_shoulders(&mut up, steps);
+            add_shoulders(&mut down, steps);
+        }
+
+        for screen_row in 0..total_rows {
+            let row = if flipped {
+                total_rows - 1 - screen_row
+            } else {
+                screen_row
+            };
+            for (bar, (&(pos_hold, neg_hold), &(pos_color, neg_color))) in
+                self.peak_hold.iter().zip(&colors).enumerate()
+            {
+                let (pos_hold, pos_color) = if full_height {
+                    (pos_hold.max(neg_hold), pos_color.max(neg_color))
+                } else {
+                    (pos_hold, pos_color)
+                };
+                if row < mid_row {
+                    let depth = mid_row - 1 - row;
+                    let height = up[bar];
+                    if hold_depth(height, pos_hold, top_rows) == Some(depth) {
+                        self.push_glyph(&mut frame, pos_hold, hold_up);
+                    } else {
+                        self.push_bar_cell(&mut frame, pos_color, height, depth, steps, !flipped);
+                    }
+                } else if row == mid_row {
+                    self.push_centerline(&mut frame, centerline);
+                } else {
+                    let depth = row - mid_row - 1;
+                    let height = down[bar];
+                    if hold_depth(height, neg_hold, bottom_rows) == Some(depth) {
+                        self.push_glyph(&mut frame, neg_hold, hold_down);
+                    } else {
+                        self.push_bar_cell(&mut frame, neg_color, height, depth, steps, flipped);
+                    }
+                }
+            }
+            frame.end_row();
+        }
+
+        self.color_levels = colors;
+        self.frame_end(&mut frame);
+        self.present(out, frame)
+    }
+
+    fn draw_scope<W: Write>(&mut self, out: &mut W, levels: &[(f32, f32)]) -> io::Result<()> {
+        if self.braille_active() {
+            return self.draw_braille_scope(out, levels);
+        }
+
+        let total_rows = self.plot_rows();
+        let mid_row = total_rows / 2;
+        let rows = trace_rows(levels, total_rows);
+
+        let trace = if self.ascii { '#' } else { '█' };
+        let centerline = self.centerline_glyph();
+        let flipped = self.flipped();
+        let mut frame = self.frame_start();
+        for screen_row in 0..total_rows {
+            let row = if flipped {
+                total_rows - 1 - screen_row
+            } else {
+                screen_row
+            };
+            for (i, &(sample_row, level)) in rows.iter().enumerate() {
+                // Fill the gap up or down to the previous sample so the line stays joined.
+                let prev_row = if i > 0 { rows[i - 1].0 } else { sample_row };
+                let span = sample_row.min(prev_row)..=sample_row.max(prev_row);
+                if span.contains(&row) {
+                    self.push_glyph(&mut frame, level, trace);
+                } else if row == mid_row && self.centerline != Centerline::Hidden {
+                    self.push_centerline(&mut frame, centerline);
+                } else {
+                    frame.push(' ');
+                }
+            }
+            frame.end_row();
+        }
+
+        self.frame_end(&mut frame);
+        self.present(out, frame)
+    }
+
+    fn draw_braille_scope<W: Write>(
+        &mut self,
+        out: &mut W,
+        levels: &[(f32, f32)],
+    ) -> io::Result<()> {
+        let mut canvas = BrailleCanvas::new(self.num_bars, self.plot_rows());
+        let (_, dot_rows) = canvas.dots();
+        let points = trace_rows(levels, dot_rows);
+        for (x, &(y, _)) in points.iter().enumerate() {
+            let prev_y = if x > 0 { points[x - 1].0 } else { y };
+            for y in y.min(prev_y)..=y.max(prev_y) {
+                canvas.set(x, y);
+            }
+        }
+
+        // Each cell takes the color of the larger swing among its two dot columns.
+        let level_at = |cell: usize| {
+            let level = |x: usize| points.get(x).map_or(0.0, |&(_, level)| level);
+            level(cell * 2).max(level(cell * 2 + 1))
+        };
+        self.write_canvas(out, canvas, |cell, _| level_at(cell))
+    }
+
+    fn draw_braille_bars<W: Write>(
+        &mut self,
+        out: &mut W,
+        smoothed: &[(f32, f32)],
+    ) -> io::Result<()> {
+        let mut canvas = BrailleCanvas::new(self.num_bars, self.plot_rows());
+        let (dot_cols, dot_rows) = canvas.dots();
+        // A one-dot centerline splitting the dot rows as `split_rows` does, or
+        // drawing upward, bars up from just below the bottom over every dot row.
+        let full_height = self.full_height();
+        let (above, below) = if full_height {
+            (dot_rows, 0)
+        } else {
+            split_rows(dot_rows)
+        };
+        let mid = above;
+        if !full_height {
+            for x in 0..dot_cols {
+                canvas.set(x, mid);
+            }
+        }
+
+        let to_dots = |level: f32, dots: usize| (level * dots as f32).round() as usize;
+        for (x, (&(pos, neg), &(pos_hold, neg_hold))) in
+            smoothed.iter().zip(&self.peak_hold).enumerate()
+        {
+            let (pos, pos_hold, neg, neg_hold) = if full_height {
+                (pos.max(neg), pos_hold.max(neg_hold), 0.0, 0.0)
+            } else {
+                (pos, pos_hold, neg, neg_hold)
+            };
+            let (up, down) = (to_dots(pos, above), to_dots(neg, below));
+            for k in 1..=up {
+                canvas.set(x, mid - k);
+            }
+            for k in 1..=down {
+                canvas.set(x, mid + k);
+            }
+
+            // A lone dot for the held peak, one gap clear of the bar.
+            let (up_hold, down_hold) = (to_dots(pos_hold, above), to_dots(neg_hold, below));
+            if self.show_peak_hold && up_hold > up + 1 {
+                canvas.set(x, mid - up_hold);
+            }
+            if self.show_peak_hold && down_hold > down + 1 {
+                canvas.set(x, mid + down_hold);
+            }
+        }
+
+        // Cells above the centerline follow the positive levels, those below the
+        // negative; full height, they all take the larger.
+        let mid_cell = mid / 4;
+        let colors = self.hold_color_bands(smoothed);
+        let level_at = |cell: usize, row: usize| {
+            let level = |x: usize| {
+                colors.get(x).map_or(0.0, |&(pos, neg)| {
+                    if row < mid_cell && !full_height {
+                        pos
+                    } else if row > mid_cell && !full_height {
+                        neg
+                    } else {
+                        pos.max(neg)
+                    }
+                })
+            };
+            level(cell * 2).max(level(cell * 2 + 1))
+        };
+        let result = self.write_canvas(out, canvas, level_at);
+        self.color_levels = colors;
+        result
+    }
+
+    fn draw_radial<W: Write>(&mut self, out: &mut W, smoothed: &[(f32, f32)]) -> io::Result<()> {
+        let (width, height) = (self.num_bars, self.plot_rows());
+        let count = smoothed.len();
+        let level = |&(pos, neg): &(f32, f32)| pos.max(neg);
+        // Tips of the held peaks, drawn only where they stand clear of the spoke.
+        let holds = smoothed.iter().zip(&self.peak_hold).map(|(bar, hold)| {
+            let hold = level(hold);
+            (self.show_peak_hold && hold > level(bar) + 0.05).then_some(hold)
+        });
+        let holds: Vec<Option<f32>> = holds.collect();
+
+        if self.braille_active() {
+            let mut canvas = BrailleCanvas::new(width, height);
+            let (dot_cols, dot_rows) = canvas.dots();
+            // Each cell takes the color of the loudest spoke crossing it.
+            let mut levels = vec![0.0f32; width * height];
+            for (i, bar) in smoothed.iter().enumerate() {
+                for (x, y) in spoke(i, count, level(bar), dot_cols, dot_rows, 1.0) {
+                    canvas.set(x, y);
+                    let cell = &mut levels[y / 4 * width + x / 2];
+                    *cell = cell.max(level(bar));
+                }
+                if let Some(hold) = holds[i]
+                    && let Some(&(x, y)) = spoke(i, count, hold, dot_cols, dot_rows, 1.0).last()
+                {
+                    canvas.set(x, y);
+                }
+            }
+            return self.write_canvas(out, canvas, |cell, row| levels[row * width + cell]);
+        }
+
+        // Cells are about twice as tall as they are wide.
+        const CELL_ASPECT: f32 = 2.0;
+        let mut bars: Vec<Option<f32>> = vec![None; width * height];
+        let mut tips: Vec<Option<f32>> = vec![None; width * height];
+        for (i, bar) in smoothed.iter().enumerate() {
+            for (x, y) in spoke(i, count, level(bar), width, height, CELL_ASPECT) {
+                let cell = &mut bars[y * width + x];
+                *cell = Some(cell.map_or(level(bar), |other| other.max(level(bar))));
+            }
+            if let Some(hold) = holds[i]
+                && let Some(&(x, y)) = spoke(i, count, hold, width, height, CELL_ASPECT).last()
+            {
+                tips[y * width + x] = Some(hold);
+            }
+        }
+
+        let (spoke_glyph, tip_glyph) = if self.ascii {
+            ('#', '.')
+        } else {
+            ('█', '·')
+        };
+        let mut frame = self.frame_start();
+        for row in 0..height {
+            for col in 0..width {
+                match (bars[row * width + col], tips[row * width + col]) {
+                    (Some(level), _) => self.push_glyph(&mut frame, level, spoke_glyph),
+                    (None, Some(hold)) => self.push_glyph(&mut frame, hold, tip_glyph),
+                    (None, None) => frame.push(' '),
+                }
+            }
+            frame.end_row();
+        }
+
+        self.frame_end(&mut frame);
+        self.present(out, frame)
+    }
+
+    fn draw_vu<W: Write>(&mut self, out: &mut W, smoothed: &[(f32, f32)]) -> io::Result<()> {
+        let width = self.num_bars;
+        let total_rows = self.plot_rows();
+        // Each meter gets an equal band of rows and is drawn up to three rows
+        // thick in the middle of it, leaving a row of space between meters.
+        let band = (total_rows / smoothed.len().max(1)).max(1);
+        let thickness = band.saturating_sub(1).clamp(1, 3);
+        let first = (band - thickness) / 2;
+
+        let steps = if self.partial_blocks && !self.ascii {
+            8
+        } else {
+            1
+        };
+        let (full, tick) = if self.ascii {
+            ('#', '|')
+        } else {
+            ('█', '│')
+        };
+
+        let mut frame = self.frame_start();
+        for row in 0..total_rows {
+            let meter = Some(row / band)
+                .filter(|_| (first..first + thickness).contains(&(row % band)))
+                .and_then(|meter| smoothed.get(meter).zip(self.peak_hold.get(meter)));
+            let Some((&(level, _), &(hold, _))) = meter else {
+                (0..width).for_each(|_| frame.push(' '));
+                frame.end_row();
+                continue;
+            };
+
+            let filled = (level * (width * steps) as f32).round() as usize;
+            let hold_col = ((hold * width as f32) as usize).min(width.saturating_sub(1));
+            let show_hold = self.show_peak_hold && hold > 0.0 && hold_col >= filled.div_ceil(steps);
+            for col in 0..width {
+                let position = (col as f32 + 0.5) / width as f32;
+                let cell = filled.saturating_sub(col * steps).min(steps);
+                if show_hold && col == hold_col {
+                    self.push_meter_glyph(&mut frame, position, tick);
+                } else if cell == 0 {
+                    frame.push(' ');
+                } else if cell == steps {
+                    self.push_meter_glyph(&mut frame, position, full);
+                } else {
+                    self.push_meter_glyph(&mut frame, position, LEFT_EIGHTHS[cell]);
+                }
+            }
+            frame.end_row();
+        }
+
+        self.frame_end(&mut frame);
+        self.present(out, frame)
+    }
+
+    // Color each cell of `canvas` by `level_at(column, row)` and write the frame.
+    fn write_canvas<W: Write>(
+        &mut self,
+        out: &mut W,
+        mut canvas: BrailleCanvas,
+        level_at: impl Fn(usize, usize) -> f32,
+    ) -> io::Result<()> {
+        let flipped = self.flipped();
+        if flipped {
+            canvas.flip();
+        }
+        let lines = canvas.render();
+        let mut frame = self.frame_start();
+        for (screen_row, line) in lines.iter().enumerate() {
+            let row = if flipped {
+                lines.len() - 1 - screen_row
+            } else {
+                screen_row
+            };
+            for (cell, glyph) in line.chars().enumerate() {
+                if glyph == BLANK_BRAILLE {
+                    // Some fonts draw the empty pattern as a visible box.
+                    frame.push(' ');
+                } else {
+                    self.push_glyph(&mut frame, level_at(cell, row), glyph);
+                }
+            }
+            frame.end_row();
+        }
+
+        self.frame_end(&mut frame);
+        self.present(out, frame)
+    }
+
+    fn draw_waterfall<W: Write>(&mut self, out: &mut W, levels: &[(f32, f32)]) -> io::Result<()> {
+        // The row about to scroll off is reused for the new one.
+        let mut row = if self.waterfall.len() >= self.plot_rows() {
+            self.waterfall.pop_back().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        row.clear();
+        row.extend(levels.iter().map(|&(level, _)| level));
+        self.waterfall.push_front(row);
+        self.waterfall.truncate(self.plot_rows());
+
+        let shades = if self.ascii {
+            ASCII_SHADES
+        } else {
+            UNICODE_SHADES
+        };
+
+        let mut frame = self.frame_start();
+        for row in &self.waterfall {
+            // Rows from before a resize are cut or padded to the current width.
+            for i in 0..self.num_bars {
+                let level = row.get(i).copied().unwrap_or(0.0);
+                let shade = (level * (shades.len() - 1) as f32).ceil() as usize;
+                match shade {
+                    0 => frame.push(' '),
+                    _ => self.push_glyph(&mut frame, level, shades[shade]),
+                }
+            }
+            frame.end_row();
+        }
+        // Blank rows until the history fills the screen, so the frame keeps its shape.
+        for _ in self.waterfall.len()..self.plot_rows() {
+            (0..self.num_bars).for_each(|_| frame.push(' '));
+            frame.end_row();
+        }
+
+        self.frame_end(&mut frame);
+        self.present(out, frame)
+    }
+}
+
+// Terminal image protocols the visualization rows can be drawn with.
+#[derive(Clone, Copy)]
+enum Graphics {
+    Sixel,
+    Kitty,
+}
+
+// Bottom of the VU meter's scale without --db.
+const VU_FLOOR_DB: f32 = -60.0;
+
+const BLANK_BRAILLE: char = '\u{2800}';
+
+// Waterfall cells from silent to full scale.
+const UNICODE_SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+const ASCII_SHADES: [char; 5] = [' ', '.', ':', '*', '#'];
+
+// Rows above and below a one-row centerline sharing `total` rows, adding up to
+// one fewer. With an odd total the halves match; with an even one the line
+// sits just below the middle, so the upper half has the extra row.
+fn split_rows(total: usize) -> (usize, usize) {
+    let above = total / 2;
+    (above, total.saturating_sub(above + 1))
+}
+
+// Raise each bar of `heights`, in half-cell `steps` of 2, by a half block where
+// a neighbour stands a whole cell or more taller, so the outline between them
+// steps down in halves rather than a staircase of whole cells. Silent bars
+// stay empty.
+fn add_shoulders(heights: &mut [usize], steps: usize) {
+    let raw = heights.to_vec();
+    for (i, height) in heights.iter_mut().enumerate() {
+        let left = i.checked_sub(1).map_or(0, |left| raw[left]);
+        let right = raw.get(i + 1).copied().unwrap_or(0);
+        if raw[i] > 0 && left.max(right) >= raw[i] + steps {
+            *height = raw[i] + steps / 2;
+        }
+    }
+}
+
+// Grid points along spoke `i` of `count`, running from the center of a `width` x
+// `height` grid for `length` (0 to 1) of the radius of the largest circle that
+// fits. Spoke 0 points up and the rest go round clockwise; `aspect` is how much
+// taller than wide each grid point is.
+fn spoke(
+    i: usize,
+    count: usize,
+    length: f32,
+    width: usize,
+    height: usize,
+    aspect: f32,
+) -> Vec<(usize, usize)> {
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+    let radius = cx.min(cy * aspect);
+    let angle =
+        std::f32::consts::TAU * i as f32 / count.max(1) as f32 - std::f32::consts::FRAC_PI_2;
+    let (sin, cos) = angle.sin_cos();
+    let reach = length.clamp(0.0, 1.0) * radius;
+
+    // Half-point steps, so no cell along the way is skipped.
+    let steps = (reach * 2.0).ceil().max(1.0) as usize;
+    let mut points: Vec<(usize, usize)> = (0..=steps)
+        .map(|k| {
+            let r = reach * k as f32 / steps as f32;
+            (cx + r * cos, cy + r * sin / aspect)
+        })
+        .filter(|&(x, y)| x >= 0.0 && y >= 0.0)
+        .map(|(x, y)| (x as usize, y as usize))
+        .filter(|&(x, y)| x < width && y < height)
+        .collect();
+    points.dedup();
+    points
+}
+
+fn db_to_amplitude(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+// A peak as dBFS to one decimal place, with silence as minus infinity.
+fn format_dbfs(peak: f32, ascii: bool) -> String {
+    if peak > 0.0 {
+        format!("{:.1}", 20.0 * peak.log10())
+    } else if ascii {
+        "-inf".to_string()
+    } else {
+        "-∞".to_string()
+    }
+}
+
+// `m:ss`, or `h:mm:ss` from an hour up.
+fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+// Blocks filled from the bottom of the cell, indexed by eighths.
+const LOWER_EIGHTHS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+// And from the left, for the VU meter.
+const LEFT_EIGHTHS: [char; 9] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
+
+// For each scope column's normalized level, the row its sample lands on among
+// `total_rows` (counting down from the top, with zero on the middle row) and
+// the sample's magnitude.
+fn trace_rows(levels: &[(f32, f32)], total_rows: usize) -> Vec<(usize, f32)> {
+    let mid_row = total_rows / 2;
+    levels
+        .iter()
+        .map(|&(pos, neg)| {
+            let level = (pos - neg).clamp(-1.0, 1.0);
+            let offset = (level * mid_row as f32).round() as isize;
+            let row = (mid_row as isize - offset).clamp(0, total_rows as isize - 1);
+            (row as usize, level.abs())
+        })
+        .collect()
+}
+
+// How far along the theme's gradient a cell at `level` is colored. The curve
+// gives quiet levels more of the gradient.
+fn color_position(level: f32) -> f32 {
+    level.clamp(0.0, 1.0).powf(COLOR_CURVE)
+}
+
+impl Visualizer {
+    // Style coloring a cell at `level` in the active theme, or `None` when
+    // color is off or the theme is `Mono`.
+    fn color_for(&self, level: f32) -> Option<Style> {
+        let stops = self.theme.stops();
+        if !self.color || stops.is_empty() {
+            return None;
+        }
+
+        let scaled = color_position(level);
+        let rgb = match self.color_depth {
+            // The palette can't blend smoothly, so snap to the theme's stops.
+            ColorDepth::Ansi256 => {
+                let band = ((scaled * stops.len() as f32) as usize).min(stops.len() - 1);
+                stops[band]
+            }
+            ColorDepth::Truecolor => blend(stops, scaled),
+        };
+        // A beat flash washes the colors out toward white.
+        let white = [rgb, (255, 255, 255)];
+        let rgb = if self.flash > 0.0 {
+            blend(&white, self.flash * 0.6)
+        } else {
+            rgb
+        };
+        Some(Style {
+            fg: Some(self.dim(rgb)),
+            ..Style::default()
+        })
+    }
+
+    // The levels to color `levels` by. The 256-color palette snaps each bar to
+    // one of the theme's stops, and a bar hovering at the edge between two would
+    // flicker between them, so a bar only moves to another stop once its level
+    // is `BAND_MARGIN` past the edge. Until then it's colored as the middle of
+    // the stop it had.
+    fn hold_color_bands(&mut self, levels: &[(f32, f32)]) -> Vec<(f32, f32)> {
+        let mut colors = std::mem::take(&mut self.color_levels);
+        colors.clear();
+        let stops = self.theme.stops().len();
+        if !self.color || stops == 0 || self.color_depth != ColorDepth::Ansi256 {
+            colors.extend_from_slice(levels);
+            return colors;
+        }
+
+        let last = stops - 1;
+        let hold = |band: &mut usize, level: f32| {
+            let position = color_position(level) * stops as f32;
+            let up = ((position - BAND_MARGIN).max(0.0) as usize).min(last);
+            let down = ((position + BAND_MARGIN) as usize).min(last);
+            if up > *band {
+                *band = up;
+            } else if down < *band {
+                *band = down;
+            }
+            if (position as usize).min(last) == *band {
+                level
+            } else {
+                ((*band as f32 + 0.5) / stops as f32).powf(1.0 / COLOR_CURVE)
+            }
+        };
+        self.color_bands.resize(levels.len(), (0, 0));
+        for (&(pos, neg), (pos_band, neg_band)) in levels.iter().zip(&mut self.color_bands) {
+            colors.push((hold(pos_band, pos), hold(neg_band, neg)));
+        }
+        colors
+    }
+
+    // `rgb` at the display's brightness.
+    fn dim(&self, (r, g, b): (u8, u8, u8)) -> (u8, u8, u8) {
+        let scale = |v: u8| (v as f32 * self.brightness).round() as u8;
+        (scale(r), scale(g), scale(b))
+    }
+
+    // Whether the bar modes draw upward over the full height.
+    fn full_height(&self) -> bool {
+        self.upward || self.centerline == Centerline::Hidden
+    }
+
+    // Whether this frame is drawn upside down.
+    fn flipped(&self) -> bool {
+        self.flip
+            && matches!(
+                self.mode,
+                VisualizerMode::Waveform
+                    | VisualizerMode::Spectrum
+                    | VisualizerMode::Mirror
+                    | VisualizerMode::Scope
+            )
+    }
+
+    // The glyph of the bar and scope modes' centerline.
+    fn centerline_glyph(&self) -> char {
+        match self.centerline {
+            Centerline::Glyph(glyph) => glyph,
+            _ if self.ascii => '-',
+            _ => '─',
+        }
+    }
+
+    // A centerline cell, drawn heavy and bright while a beat flash is strong.
+    fn push_centerline(&self, frame: &mut Frame, glyph: char) {
+        if self.flash < 0.3 {
+            return frame.push(glyph);
+        }
+        let glyph = if self.ascii { '=' } else { '━' };
+        let style = Style {
+            fg: self.color.then(|| self.dim((255, 255, 255))),
+            bold: true,
+            ..Style::default()
+        };
+        frame.push_styled(style, glyph);
+    }
+
+    // `glyph` colored for `level`.
+    fn push_glyph(&self, frame: &mut Frame, level: f32, glyph: char) {
+        match self.color_for(level) {
+            Some(style) => frame.push_styled(style, glyph),
+            None => frame.push(glyph),
+        }
+    }
+
+    // A VU meter cell `position` (0 to 1) of the way to full scale: the theme's
+    // color there, turning red over the last stretch like a hardware meter.
+    fn push_meter_glyph(&self, frame: &mut Frame, position: f32, glyph: char) {
+        const RED_FROM: f32 = 0.75;
+        const RED: (u8, u8, u8) = (230, 40, 40);
+
+        match self.color_for(position) {
+            Some(mut style) => {
+                if let Some(fg) = &mut style.fg
+                    && position > RED_FROM
+                {
+                    let red = self.dim(RED);
+                    *fg = blend(&[*fg, red], (position - RED_FROM) / (1.0 - RED_FROM));
+                }
+                frame.push_styled(style, glyph);
+            }
+            None => frame.push(glyph),
+        }
+    }
+
+    // The cell `depth` rows out from the centerline of a bar `height` steps tall,
+    // growing up from the centerline when `upward` and down from it otherwise.
+    fn push_bar_cell(
+        &self,
+        frame: &mut Frame,
+        level: f32,
+        height: usize,
+        depth: usize,
+        steps: usize,
+        upward: bool,
+    ) {
+        let filled = height.saturating_sub(depth * steps).min(steps);
+        if filled == 0 {
+            frame.push(' ');
+        } else if filled == steps {
+            self.push_glyph(frame, level, if self.ascii { '#' } else { '█' });
+        } else if upward {
+            self.push_glyph(frame, level, LOWER_EIGHTHS[filled * 8 / steps]);
+        } else {
+            self.push_hanging(frame, level, filled * 8 / steps);
+        }
+    }
+
+    // A block filled `eighths`/8 from the top of the cell. Unicode only has a few
+    // top-anchored fractions, so with color on this draws the complementary
+    // lower block in reverse video instead.
+    fn push_hanging(&self, frame: &mut Frame, level: f32, eighths: usize) {
+        // The upper half block needs no trick.
+        if eighths == 4 {
+            return self.push_glyph(frame, level, '▀');
+        }
+        match self.color_for(level) {
+            Some(style) => {
+                let style = Style {
+                    inverse: true,
+                    ..style
+                };
+                frame.push_styled(style, LOWER_EIGHTHS[8 - eighths]);
+            }
+            None => frame.push(match eighths {
+                0..=2 => '▔',
+                3..=5 => '▀',
+                _ => '█',
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn db_scale_maps_floor_to_empty_and_peak_to_full() {
+        let visualizer = Visualizer::new(VisualizerMode::Waveform).with_db(-60.0);
+        assert_eq!(visualizer.scale(1.0), 1.0);
+        assert_eq!(visualizer.scale(0.001), 0.0);
+        assert_eq!(visualizer.scale(0.0), 0.0);
+        // -30 dB is halfway up.
+        assert!((visualizer.scale(0.031_622_78) - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn linear_scale_is_the_default() {
+        let visualizer = Visualizer::new(VisualizerMode::Waveform);
+        assert_eq!(visualizer.scale(0.25), 0.25);
+        assert_eq!(visualizer.scale(2.0), 1.0);
+    }
+
+    #[test]
+    fn times_show_hours_only_when_needed() {
+        assert_eq!(format_time(Duration::from_secs(42)), "0:42");
+        assert_eq!(format_time(Duration::from_secs(195)), "3:15");
+        assert_eq!(format_time(Duration::from_secs(3725)), "1:02:05");
+    }
+
+    #[test]
+    fn readout_shows_each_channel_in_dbfs() {
+        assert_eq!(format_dbfs(1.0, false), "0.0");
+        assert_eq!(format_dbfs(0.5, false), "-6.0");
+        assert_eq!(format_dbfs(0.0, false), "-∞");
+        assert_eq!(format_dbfs(0.0, true), "-inf");
+
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+            .with_bars(8)
+            .with_height(4)
+            .with_readout(true);
+        let mut out = Vec::new();
+        visualizer
+            .render_stereo(&mut out, &[0.5, -0.25], &[0.0, 0.0])
+            .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("L: -6.0  R: -∞"), "{:?}", text);
+    }
+
+    #[test]
+    fn short_buffers_still_fill_every_bar() {
+        let samples: Vec<f32> = (1..=10).map(|n| n as f32 / 10.0).collect();
+        let mut columns = Vec::new();
+        waveform_columns(&samples, 64, LevelMode::PeakAvg, &mut columns);
+        assert_eq!(columns.len(), 64);
+        assert!(columns.iter().all(|&(up, _)| up > 0.0));
+        assert_eq!(columns[0], (0.1, 0.0));
+        assert_eq!(columns[63], (1.0, 0.0));
+
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform).with_bars(64);
+        let mut out = Vec::new();
+        visualizer.render(&mut out, &samples).unwrap();
+        assert!(!out.is_empty());
+        assert!(visualizer.levels.iter().all(|&(up, _)| up > 0.0));
+    }
+
+    #[test]
+    fn analyze_normalizes_to_the_loudest_bar() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+            .with_bars(4)
+            .with_smoothing(1.0);
+        let mut samples = vec![0.5; 512];
+        samples.extend([-0.25; 512]);
+
+        assert_eq!(
+            visualizer.analyze(&samples),
+            [(1.0, 0.0), (1.0, 0.0), (0.0, 0.5), (0.0, 0.5)]
+        );
+        // Nothing new to analyze keeps what was there.
+        assert_eq!(visualizer.analyze(&[]).len(), 4);
+    }
+
+    #[test]
+    fn analyze_eases_toward_new_levels() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+            .with_bars(1)
+            .with_smoothing(0.5);
+        assert_eq!(visualizer.analyze(&[0.5; 256]), [(0.5, 0.0)]);
+        assert_eq!(visualizer.analyze(&[0.5; 256]), [(0.75, 0.0)]);
+    }
+
+    #[test]
+    fn vu_reads_rms_against_full_scale_per_channel() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Vu).with_smoothing(1.0);
+        // -20 dBFS is a third of the way down a 60 dB scale.
+        let &[(level, _)] = visualizer.analyze(&[0.1, -0.1, 0.1, -0.1]) else {
+            panic!("one meter for one channel");
+        };
+        assert!((level - 2.0 / 3.0).abs() < 1e-4, "level = {}", level);
+
+        visualizer.analyze_channels(&[&[1.0; 64], &[0.0; 64]]);
+        assert_eq!(visualizer.levels, [(1.0, 1.0), (0.0, 0.0)]);
+        let mut out = Vec::new();
+        visualizer.draw(&mut out).unwrap();
+        assert!(!out.is_empty());
+    }
+
+    #[test]
+    fn from_config_applies_every_setting() {
+        let config = VisualizerConfig::default()
+            .mode(VisualizerMode::Spectrum)
+            .bars(128)
+            .height(30)
+            .theme(Theme::Fire)
+            .gamma(2.0)
+            .db_floor(Some(-40.0));
+        let visualizer = Visualizer::from_config(config);
+
+        assert_eq!(visualizer.mode(), VisualizerMode::Spectrum);
+        assert_eq!((visualizer.num_bars, visualizer.max_height), (128, 30));
+        assert_eq!(
+            (visualizer.fixed_bars, visualizer.fixed_height),
+            (Some(128), Some(30))
+        );
+        assert_eq!(visualizer.theme, Theme::Fire);
+        assert_eq!(visualizer.gamma, 2.0);
+        assert_eq!(visualizer.db_floor, Some(-40.0));
+    }
+
+    #[test]
+    fn fixed_gain_skips_the_auto_gain_and_clips() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+            .with_bars(2)
+            .with_smoothing(1.0)
+            .with_gain(2.0);
+        assert_eq!(visualizer.analyze(&[0.25, -0.1]), [(0.5, 0.0), (0.0, 0.2)]);
+        assert_eq!(visualizer.analyze(&[0.8, 0.8]), [(1.0, 0.0), (1.0, 0.0)]);
+        // The same quiet buffer stays the same height after a loud one.
+        assert_eq!(visualizer.analyze(&[0.25, -0.1]), [(0.5, 0.0), (0.0, 0.2)]);
+    }
+
+    #[test]
+    fn rows_split_around_the_centerline_for_either_parity() {
+        for total in [3, 5, 21] {
+            let (above, below) = split_rows(total);
+            assert_eq!(above + below + 1, total);
+            assert_eq!(above, below);
+        }
+        for total in [2, 4, 20] {
+            let (above, below) = split_rows(total);
+            assert_eq!(above + below + 1, total);
+            assert_eq!(above, below + 1);
+        }
+        assert_eq!(split_rows(1), (0, 0));
+    }
+
+    #[test]
+    fn full_scale_reaches_both_edges_at_an_even_height() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+            .with_bars(1)
+            .with_height(6)
+            .with_smoothing(1.0)
+            .with_peak_hold(false);
+        visualizer.render(&mut io::sink(), &[1.0, -1.0]).unwrap();
+        let column: String = visualizer
+            .last_frame
+            .as_ref()
+            .unwrap()
+            .rows()
+            .map(|row| row[0].glyph)
+            .collect();
+        assert_eq!(column, "███─██");
+    }
+
+    #[test]
+    fn flipping_turns_the_rows_upside_down() {
+        let rows = |flip: bool, braille: bool| {
+            let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+                .with_bars(4)
+                .with_height(7)
+                .with_smoothing(1.0)
+                .with_partial_blocks(false)
+                .with_braille(braille)
+                .with_flip(flip);
+            let samples = [0.9, -0.3, 0.2, -1.0, 0.6, 0.0, 0.4, -0.5];
+            visualizer.render(&mut io::sink(), &samples).unwrap();
+            let frame = visualizer.last_frame.take().unwrap();
+            frame
+                .rows()
+                .map(|row| row.iter().map(|cell| cell.glyph).collect::<String>())
+                .collect::<Vec<_>>()
+        };
+
+        // Peak markers turn over with the bars.
+        let turn = |c: char| match c {
+            '▁' => '▔',
+            '▔' => '▁',
+            c => c,
+        };
+        let flipped: Vec<String> = rows(true, false)
+            .iter()
+            .rev()
+            .map(|row| row.chars().map(turn).collect())
+            .collect();
+        assert_eq!(flipped, rows(false, false));
+
+        // Braille dots mirror within each cell too, so compare the dot counts.
+        let dots = |rows: Vec<String>| -> Vec<u32> {
+            let dots = |glyph: char| (glyph as u32).saturating_sub(0x2800).count_ones();
+            rows.iter().map(|row| row.chars().map(dots).sum()).collect()
+        };
+        let mut flipped = dots(rows(true, true));
+        flipped.reverse();
+        assert_eq!(flipped, dots(rows(false, true)));
+    }
+
+    #[test]
+    fn split_mode_stacks_the_spectrum_over_the_waveform() {
+        let rows = |mode: VisualizerMode, height: usize| {
+            let mut visualizer = Visualizer::new(mode)
+                .with_bars(16)
+                .with_height(height)
+                .with_smoothing(0.5);
+            let samples: Vec<f32> = (0..2048).map(|i| (i as f32 * 0.2).sin()).collect();
+            for _ in 0..3 {
+                visualizer.render(&mut io::sink(), &samples).unwrap();
+            }
+            let frame = visualizer.last_frame.take().unwrap();
+            frame.rows().map(|row| row.to_vec()).collect::<Vec<_>>()
+        };
+
+        // Each half keeps its own easing and peaks, as if it had the screen.
+        let mut stacked = rows(VisualizerMode::Spectrum, 5);
+        stacked.extend(rows(VisualizerMode::Waveform, 4));
+        assert!(stacked == rows(VisualizerMode::Split, 9));
+        assert_eq!(VisualizerMode::Vu.next(), VisualizerMode::Split);
+    }
+
+    #[test]
+    fn feeding_draws_a_frame_per_full_buffer() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+            .with_bars(8)
+            .with_height(5)
+            .with_feed_samples(16);
+        assert_eq!(visualizer.feed(&[0.5; 10]), None);
+        let frame = visualizer.feed(&[0.5; 10]).unwrap();
+        assert!(frame.contains('█'));
+        assert_eq!(visualizer.fed.len(), 4);
+
+        // Several frames' worth at once draw the latest and keep the rest.
+        assert!(visualizer.feed(&[-0.5; 40]).is_some());
+        assert_eq!(visualizer.fed.len(), 12);
+        visualizer.reset();
+        assert!(visualizer.fed.is_empty());
+    }
+
+    #[test]
+    fn smooth_edges_step_down_in_half_blocks() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+            .with_bars(3)
+            .with_height(6)
+            .with_gain(1.0)
+            .with_smoothing(1.0)
+            .with_peak_hold(false)
+            .with_upward(true)
+            .with_smooth_edges(true);
+        let samples = [1.0, 1.0, 0.3, 0.3, 0.0, 0.0];
+        visualizer.render(&mut io::sink(), &samples).unwrap();
+        let frame = visualizer.last_frame.take().unwrap();
+        let rows: Vec<String> = frame
+            .rows()
+            .map(|row| row.iter().map(|cell| cell.glyph).collect())
+            .collect();
+        // The middle bar rounds to two cells and gains a shoulder beside the
+        // first; the silent one doesn't.
+        assert_eq!(rows, ["█  ", "█  ", "█  ", "█▄ ", "██ ", "██ "]);
+
+        // Half a cell takes the upper half block when bars hang down.
+        let mut visualizer = visualizer.with_flip(true);
+        visualizer.render(&mut io::sink(), &[0.25, 0.25]).unwrap();
+        let frame = visualizer.last_frame.take().unwrap();
+        let tip = frame.rows().map(|row| row[0].glyph).nth(1);
+        assert_eq!(tip, Some('▀'));
+    }
+
+    #[test]
+    fn upward_bars_take_the_full_height() {
+        assert_eq!("none".parse(), Ok(Centerline::Hidden));
+        assert_eq!("·".parse(), Ok(Centerline::Glyph('·')));
+        assert!("--".parse::<Centerline>().is_err());
+
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+            .with_bars(4)
+            .with_height(6)
+            .with_centerline(Centerline::Hidden);
+        visualizer.render(&mut io::sink(), &[0.5, -1.0]).unwrap();
+        let rows: Vec<String> = visualizer
+            .last_frame
+            .as_ref()
+            .unwrap()
+            .rows()
+            .map(|row| row.iter().map(|cell| cell.glyph).collect())
+            .collect();
+
+        assert_eq!(rows.len(), 6);
+        assert!(rows.iter().all(|row| !row.contains('─')), "{:?}", rows);
+        assert_eq!(rows[5], "████");
+
+        // Upward keeps the shape whatever the centerline.
+        let mut upward = Visualizer::new(VisualizerMode::Waveform)
+            .with_bars(4)
+            .with_height(6)
+            .with_upward(true);
+        upward.render(&mut io::sink(), &[0.5, -1.0]).unwrap();
+        let same = upward.last_frame.as_ref().unwrap().rows().eq(visualizer
+            .last_frame
+            .as_ref()
+            .unwrap()
+            .rows());
+        assert!(same);
+    }
+
+    #[test]
+    fn clipping_shows_a_marker_for_a_second() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+            .with_bars(20)
+            .with_height(5);
+        visualizer.set_sample_rate(10);
+        visualizer.set_header(Some("Song".to_string()));
+        let mut frame = |samples: &[f32]| {
+            visualizer.render(&mut io::sink(), samples).unwrap();
+            let header = visualizer
+                .last_frame
+                .as_ref()
+                .unwrap()
+                .rows()
+                .next()
+                .unwrap();
+            let header: String = header.iter().map(|cell| cell.glyph).collect();
+            header.ends_with(" CLIP")
+        };
+
+        assert!(!frame(&[0.5; 5]));
+        assert!(frame(&[1.0, 0.0, 0.0, 0.0, 0.0]));
+        // Still up half a second later, gone after another.
+        assert!(frame(&[0.5; 5]));
+        assert!(!frame(&[0.5; 10]));
+    }
+
+    #[test]
+    fn a_small_terminal_gets_a_notice_instead_of_a_frame() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+            .with_bars(20)
+            .with_height(5);
+        visualizer.too_small = true;
+        visualizer.width = 12;
+
+        let mut out = Vec::new();
+        visualizer.render(&mut out, &[0.5; 64]).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.ends_with("terminal too\r\n"), "{:?}", text);
+
+        // Left alone on screen until something changes.
+        let mut out = Vec::new();
+        visualizer.render(&mut out, &[0.5; 64]).unwrap();
+        assert!(out.is_empty());
+
+        // Then back to frames once there's room.
+        visualizer.too_small = false;
+        visualizer.width = 20;
+        let mut out = Vec::new();
+        visualizer.render(&mut out, &[0.5; 64]).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains('█'));
+    }
+
+    #[test]
+    fn frequency_labels_sit_under_their_bars() {
+        let labels = |bars: usize| {
+            let mut visualizer = Visualizer::new(VisualizerMode::Spectrum)
+                .with_bars(bars)
+                .with_height(8)
+                .with_freq_labels(true);
+            visualizer.render(&mut io::sink(), &[0.0; 256]).unwrap();
+            let last = visualizer
+                .last_frame
+                .as_ref()
+                .unwrap()
+                .rows()
+                .last()
+                .unwrap();
+            last.iter().map(|cell| cell.glyph).collect::<String>()
+        };
+
+        assert_eq!(
+            labels(64),
+            "      60             250          1k           4k           16k "
+        );
+        // Thinned out where they'd run together.
+        assert_eq!(labels(12), "60 250  4k  ");
+    }
+
+    #[test]
+    fn attack_release_rises_fast_and_falls_slowly() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+            .with_bars(1)
+            .with_gain(1.0)
+            .with_gate(-120.0)
+            .with_level_mode(LevelMode::Peak)
+            .with_envelope(EnvelopeMode::AttackRelease)
+            .with_attack(Duration::ZERO)
+            .with_release(Duration::from_millis(100));
+        visualizer.set_sample_rate(100);
+
+        // No attack time: straight up.
+        assert_eq!(visualizer.analyze(&[0.8; 10])[0].0, 0.8);
+        // A tenth of a second of silence is one release time constant.
+        let level = visualizer.analyze(&[0.0; 10])[0].0;
+        assert!(
+            (level - 0.8 / std::f32::consts::E).abs() < 1e-4,
+            "{}",
+            level
+        );
+    }
+
+    #[test]
+    fn smoothing_time_eases_alike_at_any_frame_rate() {
+        // The level after a tenth of a second of full scale, drawn in `frames`.
+        let level_after = |frames: usize| {
+            let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+                .with_bars(1)
+                .with_gain(1.0)
+                .with_gate(-120.0)
+                .with_level_mode(LevelMode::Peak)
+                .with_smoothing_time(Duration::from_millis(50));
+            visualizer.set_sample_rate(1200);
+            let samples = vec![1.0; 120 / frames];
+            (0..frames).fold(0.0, |_, _| visualizer.analyze(&samples)[0].0)
+        };
+
+        // Two time constants in: 1 - 1/e² of the way, however it's divided up.
+        let expected = 1.0 - (-2.0f32).exp();
+        for frames in [2, 3, 6, 12] {
+            let level = level_after(frames);
+            assert!(
+                (level - expected).abs() < 1e-4,
+                "{} frames: {}",
+                frames,
+                level
+            );
+        }
+    }
+
+    #[test]
+    fn peaks_fall_alike_at_any_frame_rate() {
+        // The running peak and the hold a fifth of a second after full scale,
+        // with the silence since drawn in `frames`.
+        let after = |frames: usize| {
+            let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+                .with_bars(1)
+                .with_gate(-120.0)
+                .with_level_mode(LevelMode::Peak)
+                .with_smoothing_time(Duration::ZERO);
+            visualizer.set_sample_rate(1200);
+            visualizer.analyze(&[1.0; 40]);
+            let silence = vec![0.0; 240 / frames];
+            for _ in 0..frames {
+                visualizer.analyze(&silence);
+            }
+            (visualizer.peak, visualizer.peak_hold[0].0)
+        };
+
+        // Six reference frames' worth of decay and fall.
+        let expected = (0.92f32.powi(6), 1.0 - 6.0 * 0.015);
+        for frames in [2, 3, 6, 12] {
+            let (peak, hold) = after(frames);
+            assert!(
+                (peak - expected.0).abs() < 1e-4,
+                "{} frames: {}",
+                frames,
+                peak
+            );
+            assert!(
+                (hold - expected.1).abs() < 1e-4,
+                "{} frames: {}",
+                frames,
+                hold
+            );
+        }
+    }
+
+    #[test]
+    fn reset_restores_the_initial_state() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Waterfall)
+            .with_bars(8)
+            .with_height(5);
+        visualizer.render(&mut io::sink(), &[0.9; 512]).unwrap();
+        assert!(!visualizer.waterfall.is_empty());
+        visualizer.set_mode(VisualizerMode::Waveform);
+        assert!(visualizer.waterfall.is_empty());
+
+        visualizer.render(&mut io::sink(), &[0.9; 512]).unwrap();
+        assert_ne!(visualizer.peak, INITIAL_PEAK);
+        assert!(!visualizer.prev_columns.is_empty());
+        assert!(!visualizer.peak_hold.is_empty());
+
+        visualizer.reset();
+        assert_eq!(visualizer.peak, INITIAL_PEAK);
+        assert!(visualizer.prev_columns.is_empty());
+        assert!(visualizer.peak_hold.is_empty());
+        assert_eq!(visualizer.flash, 0.0);
+    }
+
+    #[test]
+    fn brightness_scales_every_color() {
+        let themed = |brightness| {
+            let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+                .with_theme(Theme::Classic)
+                .with_brightness(brightness);
+            visualizer.color = true;
+            visualizer
+        };
+        let (r, g, b) = themed(1.0).color_for(1.0).unwrap().fg.unwrap();
+        let half = |v: u8| (v as f32 * 0.5).round() as u8;
+
+        assert_eq!(
+            themed(0.5).color_for(1.0).unwrap().fg,
+            Some((half(r), half(g), half(b)))
+        );
+        assert_eq!(themed(0.0).color_for(1.0).unwrap().fg, Some((0, 0, 0)));
+        assert_eq!(themed(7.0).brightness, 1.0);
+    }
+
+    #[test]
+    fn bars_change_palette_stops_only_past_a_margin() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform)
+            .with_theme(Theme::Classic)
+            .with_color_depth(ColorDepth::Ansi256);
+        visualizer.color = true;
+        let stops = Theme::Classic.stops();
+        // The level `position` stops along the gradient.
+        let at = |position: f32| (position / stops.len() as f32).powf(1.0 / COLOR_CURVE);
+        let mut stop_for = |position: f32| {
+            let colors = visualizer.hold_color_bands(&[(at(position), 0.0)]);
+            let stop = visualizer.color_for(colors[0].0).unwrap().fg.unwrap();
+            visualizer.color_levels = colors;
+            stops.iter().position(|&rgb| rgb == stop).unwrap()
+        };
+
+        assert_eq!(stop_for(0.5), 0);
+        assert_eq!(stop_for(1.05), 0);
+        assert_eq!(stop_for(1.2), 1);
+        assert_eq!(stop_for(0.95), 1);
+        assert_eq!(stop_for(0.8), 0);
+        assert_eq!(stop_for(4.9), 4);
+    }
+
+    #[test]
+    fn gamma_bends_heights_but_keeps_the_ends() {
+        let visualizer = Visualizer::new(VisualizerMode::Waveform).with_gamma(0.5);
+        assert_eq!(visualizer.scale(0.25), 0.5);
+        assert_eq!(visualizer.scale(0.0), 0.0);
+        assert_eq!(visualizer.scale(1.0), 1.0);
+
+        let visualizer = Visualizer::new(VisualizerMode::Waveform).with_gamma(2.0);
+        assert_eq!(visualizer.scale(0.5), 0.25);
+    }
+
+    #[test]
+    fn a_jump_in_energy_flashes_and_then_fades() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform).with_beat_flash(true);
+        let quiet = vec![0.05; 1024];
+        let loud = vec![0.5; 1024];
+        for _ in 0..50 {
+            visualizer.detect_beat(&[&quiet]);
+        }
+        assert_eq!(visualizer.flash, 0.0);
+
+        visualizer.detect_beat(&[&loud]);
+        assert_eq!(visualizer.flash, 1.0);
+        for _ in 0..20 {
+            visualizer.detect_beat(&[&quiet]);
+        }
+        assert_eq!(visualizer.flash, 0.0);
+    }
+
+    #[test]
+    fn steady_audio_never_flashes() {
+        let mut visualizer = Visualizer::new(VisualizerMode::Waveform).with_beat_flash(true);
+        let tone: Vec<f32> = (0..1024).map(|n| (n as f32 * 0.1).sin()).collect();
+        for _ in 0..200 {
+            visualizer.detect_beat(&[&tone]);
+            assert_eq!(visualizer.flash, 0.0);
+        }
+    }
+
+    #[test]
+    fn silent_spoke_is_just_the_center() {
+        assert_eq!(spoke(3, 8, 0.0, 20, 10, 2.0), [(10, 5)]);
+    }
+
+    #[test]
+    fn full_spokes_reach_the_edge_of_the_circle() {
+        // 20x10 cells twice as tall as wide: a radius of 10 columns or 5 rows.
+        let up = spoke(0, 4, 1.0, 20, 10, 2.0);
+        assert_eq!(up.first(), Some(&(10, 5)));
+        assert_eq!(up.last(), Some(&(10, 0)));
+        assert!(up.iter().all(|&(x, _)| x == 10));
+
+        let right = spoke(1, 4, 1.0, 20, 10, 2.0);
+        assert_eq!(right.last(), Some(&(19, 5)));
+        assert!(right.iter().all(|&(_, y)| y == 5));
+    }
+}
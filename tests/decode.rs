@@ -0,0 +1,121 @@
+//! Decoding a bundled WAV file and rendering it, start to finish.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use echoes::{
+    ChannelMode, EmitFormat, MonoMix, Outcome, PlayOptions, Visualizer, VisualizerMode,
+    decode_file, play_playlist,
+};
+
+// A quarter second of a 440 Hz sine, 16-bit mono at 8 kHz.
+const SINE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/sine.wav");
+
+// Everything off, as fast as it decodes.
+fn options() -> PlayOptions {
+    PlayOptions {
+        audio: false,
+        channel_mode: ChannelMode::Mono,
+        mono_mix: MonoMix::Average,
+        channel: None,
+        controls: false,
+        looping: false,
+        gapless: false,
+        start: None,
+        end: None,
+        stop_at: None,
+        fps: None,
+        window: None,
+        hop: None,
+        realtime: false,
+    }
+}
+
+#[test]
+fn a_sine_wav_renders_waveform_frames() {
+    let mut visualizer = Visualizer::new(VisualizerMode::Waveform);
+    let mut out = Vec::new();
+
+    let outcome = decode_file(Path::new(SINE), &mut visualizer, &options(), &mut out).unwrap();
+
+    assert_eq!(outcome, Outcome::Finished);
+    let text = String::from_utf8(out).unwrap();
+    assert!(text.contains('█'), "no bars drawn in {:?}", text);
+}
+
+// The time stamped on each frame emitted for `options`.
+fn frame_times(options: &PlayOptions) -> Vec<f64> {
+    let mut visualizer = Visualizer::new(VisualizerMode::Waveform).with_emit(EmitFormat::Csv);
+    let mut out = Vec::new();
+    decode_file(Path::new(SINE), &mut visualizer, options, &mut out).unwrap();
+    String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').next().unwrap().parse().unwrap())
+        .collect()
+}
+
+#[test]
+fn start_and_end_trim_the_frames() {
+    // The file decodes as two packets, the first ending at 0.144 seconds.
+    assert_eq!(frame_times(&options()), [0.144, 0.25]);
+
+    let start = PlayOptions {
+        start: Some(Duration::from_millis(150)),
+        ..options()
+    };
+    assert_eq!(frame_times(&start), [0.25]);
+
+    let end = PlayOptions {
+        end: Some(Duration::from_millis(100)),
+        ..options()
+    };
+    assert_eq!(frame_times(&end), [0.144]);
+}
+
+#[test]
+fn windows_are_drawn_whatever_the_packet_size() {
+    // Windows of 1000 samples at 8 kHz, one every 250: the first packet fills
+    // one, and what it leaves with the second fills four more. Each is timed
+    // by its own last sample rather than by the packet it came in.
+    let windowed = PlayOptions {
+        window: Some(1000),
+        hop: Some(250),
+        ..options()
+    };
+    assert_eq!(frame_times(&windowed), [0.125, 0.156, 0.188, 0.219, 0.25]);
+}
+
+#[test]
+fn playlists_stop_once_the_time_is_up() {
+    let tracks = [PathBuf::from(SINE), PathBuf::from(SINE)];
+    let options = PlayOptions {
+        stop_at: Some(Instant::now()),
+        ..options()
+    };
+    let mut visualizer = Visualizer::new(VisualizerMode::Waveform).with_emit(EmitFormat::Csv);
+    let mut out = Vec::new();
+
+    let outcome = play_playlist(&tracks, &mut visualizer, &options, &mut out).unwrap();
+
+    assert_eq!(outcome, Outcome::TimeUp);
+    assert!(out.is_empty());
+}
+
+#[test]
+fn gapless_playlists_play_every_track() {
+    let tracks = [PathBuf::from(SINE), PathBuf::from(SINE)];
+    let options = PlayOptions {
+        gapless: true,
+        ..options()
+    };
+    let mut visualizer = Visualizer::new(VisualizerMode::Waveform).with_emit(EmitFormat::Csv);
+    let mut out = Vec::new();
+
+    let outcome = play_playlist(&tracks, &mut visualizer, &options, &mut out).unwrap();
+
+    assert_eq!(outcome, Outcome::Finished);
+    // The header, then both packets of each track.
+    assert_eq!(String::from_utf8(out).unwrap().lines().count(), 5);
+}
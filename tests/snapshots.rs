@@ -0,0 +1,79 @@
+//! Rendered frames checked against the text in `tests/snapshots`, so a change
+//! to the layout shows up in review. Set `UPDATE_SNAPSHOTS=1` to rewrite them.
+
+use std::fs;
+use std::path::Path;
+
+use echoes::{Visualizer, VisualizerMode};
+
+// A small waveform display that draws levels as they are: no auto-gain, no
+// easing between frames and no peak markers.
+fn visualizer() -> Visualizer {
+    Visualizer::new(VisualizerMode::Waveform)
+        .with_bars(16)
+        .with_height(9)
+        .with_gain(1.0)
+        .with_smoothing(1.0)
+        .with_peak_hold(false)
+}
+
+// `bytes` as the text it puts on screen, one line per row, without escapes.
+fn screen_text(bytes: &[u8]) -> String {
+    let text = String::from_utf8(bytes.to_vec()).unwrap();
+    let mut plain = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            // A control sequence runs to its final byte, `@` through `~`.
+            '\x1B' => {
+                if chars.next() == Some('[') {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+            }
+            '\r' => {}
+            c => plain.push(c),
+        }
+    }
+    plain
+}
+
+fn assert_snapshot(name: &str, samples: &[f32]) {
+    let mut out = Vec::new();
+    visualizer().render(&mut out, samples).unwrap();
+    let actual = screen_text(&out);
+
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("{}: {} (run with UPDATE_SNAPSHOTS=1)", path.display(), err));
+    assert!(
+        actual == expected,
+        "{} changed; expected:\n{}\nbut drew:\n{}",
+        name,
+        expected,
+        actual
+    );
+}
+
+#[test]
+fn silence() {
+    assert_snapshot("silence", &[0.0; 256]);
+}
+
+#[test]
+fn half_scale_square_wave() {
+    let samples: Vec<f32> = (0..256)
+        .map(|i| if i / 4 % 2 == 0 { 0.5 } else { -0.5 })
+        .collect();
+    assert_snapshot("half_scale_square", &samples);
+}